@@ -0,0 +1,283 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Args, Parser, Subcommand};
+use nitrogen::{
+    check_compatibility, AccessPolicy, BiConnect, BiConnnectionOpener, CodecConfig, Compatibility, DynamicClient, Principal, ReflectionClient,
+    RpcServiceClient, SchemaChange, ServiceSchema, Session,
+};
+use nitrogen_extra::Gateway;
+use nitrogen_quic::{QuicConnect, CA_CERT_PEM, MY_CERT_PEM, MY_KEY_PEM};
+use tokio::net::TcpListener;
+
+/// nitrogen 服务的命令行工具
+#[derive(Parser)]
+#[command(name = "nitrogen-cli")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 对比新旧服务描述，存在不兼容的变更时以状态码 1 退出
+    ///
+    /// 描述中没有自定义类型的字段与变体，用到自定义类型时以状态码 2 退出，
+    /// 人工确认这些类型的编码没有变化后加 `--allow-unverified` 重新检查。
+    CheckSchema {
+        /// 已部署版本的描述，单个服务或服务数组的 JSON
+        old: PathBuf,
+        /// 待部署版本的描述
+        new: PathBuf,
+        /// 无法验证的自定义类型不影响退出状态
+        #[arg(long)]
+        allow_unverified: bool,
+    },
+    /// 按方法名调用，参数与返回值以 JSON 表示
+    ///
+    /// 参数按位置传递；结构体在 MessagePack 中编码为数组，因此结构体参数也以数组表示。
+    Call {
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        service: String,
+        /// 方法的线上名称
+        method: String,
+        /// 参数的 JSON 数组，如 `[1, "name"]`
+        #[arg(default_value = "[]")]
+        args: String,
+    },
+    /// 列出服务的方法，默认通过服务端的反射服务获取
+    List {
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        /// 只列出该服务
+        service: Option<String>,
+        /// 从描述文件读取，而不是连接服务端
+        #[arg(long)]
+        schema: Option<PathBuf>,
+    },
+    /// 在本地提供 HTTP/JSON 网关，把 `POST /{Service}/{method}` 转发给服务端
+    ///
+    /// 服务端提供反射服务时从中读取服务描述，此时参数也可以是以参数名为键的 JSON 对象。
+    /// 转发的请求带着本工具的客户端证书，HTTP 调用方只能调用 `--allow` 列出的方法。
+    Gateway {
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        /// HTTP 监听地址
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// 允许 HTTP 调用方调用的方法，`Service/method` 或 `Service/*`，可以重复
+        #[arg(long = "allow", required = true)]
+        allow: Vec<String>,
+        /// 转发的服务
+        #[arg(required = true)]
+        services: Vec<String>,
+    },
+}
+
+/// 连接服务端的参数
+#[derive(Args)]
+struct ConnectionArgs {
+    #[arg(long, default_value = "127.0.0.1:31234")]
+    addr: SocketAddr,
+    #[arg(long, default_value = CA_CERT_PEM)]
+    ca: PathBuf,
+    #[arg(long, default_value = MY_CERT_PEM)]
+    cert: PathBuf,
+    #[arg(long, default_value = MY_KEY_PEM)]
+    key: PathBuf,
+    /// 校验服务端证书时使用的服务器名称
+    #[arg(long, default_value = "localhost")]
+    server_name: String,
+    /// 握手与请求的超时，单位为秒
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::CheckSchema { old, new, allow_unverified } => {
+            let changes = check_schema_files(&old, &new)?;
+            for change in changes.iter() {
+                println!("{}", change);
+            }
+
+            let breaking = changes.iter().filter(|change| change.is_breaking()).count();
+            let unverified = changes.iter().filter(|change| change.is_unverified()).count();
+            println!("{} change(s), {} breaking, {} unverified", changes.len(), breaking, unverified);
+            if breaking > 0 {
+                std::process::exit(1);
+            }
+            if unverified > 0 && !allow_unverified {
+                eprintln!("custom types are compared by name only; check them and rerun with --allow-unverified");
+                std::process::exit(2);
+            }
+        }
+        Command::Call {
+            connection,
+            service,
+            method,
+            args,
+        } => {
+            let args = serde_json::from_str::<Vec<serde_json::Value>>(&args).map_err(|err| anyhow::anyhow!("args must be a JSON array: {}", err))?;
+            match call(&connection, &service, &method, args).await? {
+                Ok(value) => println!("{}", serde_json::to_string_pretty(&value)?),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::List { connection, service, schema } => {
+            let schemas = match schema {
+                Some(path) => load_schemas(&path)?,
+                None => list_services(&connection).await?,
+            };
+
+            let schemas = schemas
+                .iter()
+                .filter(|schema| match &service {
+                    Some(service) => schema.name == service.as_str(),
+                    None => true,
+                })
+                .collect::<Vec<_>>();
+            if let (Some(service), true) = (&service, schemas.is_empty()) {
+                anyhow::bail!("service `{}` not found", service);
+            }
+            for schema in schemas {
+                print_schema(schema);
+            }
+        }
+        Command::Gateway {
+            connection,
+            listen,
+            allow,
+            services,
+        } => gateway(&connection, listen, &services, allow).await?,
+    }
+
+    Ok(())
+}
+
+// --- call / list ---
+
+async fn connect(connection: &ConnectionArgs, services: &[&str]) -> anyhow::Result<Session> {
+    let mut client = QuicConnect::bind_with_certs("0.0.0.0:0".parse()?, &connection.ca, &connection.cert, &connection.key)
+        .await?
+        .with_server_name(connection.server_name.as_str());
+
+    let stream = client.connect(connection.addr).await?.open().await?;
+    let config = CodecConfig {
+        handshake_timeout: Duration::from_secs(connection.timeout),
+        ..Default::default()
+    };
+    Ok(Session::connect(stream, services, config).await?)
+}
+
+/// 返回值不按类型解码；连接失败时返回外层的错误，服务端返回的错误在内层
+async fn call(connection: &ConnectionArgs, service: &str, method: &str, args: Vec<serde_json::Value>) -> anyhow::Result<nitrogen::Result<serde_json::Value>> {
+    let session = connect(connection, &[service]).await?;
+    let client = DynamicClient::with_session(&session).with_timeout(Duration::from_secs(connection.timeout));
+    Ok(client.call_json(service, method, args).await)
+}
+
+async fn list_services(connection: &ConnectionArgs) -> anyhow::Result<Vec<ServiceSchema>> {
+    let session = connect(connection, &[ReflectionClient::NAME]).await?;
+    Ok(ReflectionClient::with_session(&session).list_services().await?)
+}
+
+/// `Service.method(arg: Type = default, ...) -> Return`
+fn print_schema(schema: &ServiceSchema) {
+    for method in schema.methods.iter() {
+        let args = method
+            .args
+            .iter()
+            .map(|arg| match &arg.default {
+                Some(default) => format!("{}: {} = {}", arg.name, arg.ty, default),
+                None => format!("{}: {}", arg.name, arg.ty),
+            })
+            .collect::<Vec<_>>();
+        let id = method.id.map(|id| format!(" #{}", id)).unwrap_or_default();
+        println!("{}.{}({}) -> {}{}", schema.name, method.name, args.join(", "), method.returns, id);
+        for line in method.docs.lines().filter(|line| !line.is_empty()) {
+            println!("    {}", line.trim());
+        }
+    }
+}
+
+// --- gateway ---
+
+/// 所有服务共用一条流，连接断开后请求以 502 返回
+async fn gateway(connection: &ConnectionArgs, listen: SocketAddr, services: &[String], allow: Vec<String>) -> anyhow::Result<()> {
+    let mut names = services.iter().map(String::as_str).collect::<Vec<_>>();
+    names.push(ReflectionClient::NAME);
+    let session = connect(connection, &names).await?;
+
+    let reflection = ReflectionClient::with_session(&session);
+    let client = DynamicClient::with_session(&session).with_timeout(Duration::from_secs(connection.timeout));
+    let policy = AccessPolicy::new().allow(Principal::any(), allow);
+    let mut gateway = Gateway::new();
+    for service in services {
+        let schema = reflection.describe_service(service.clone()).await.ok().flatten();
+        if schema.is_none() {
+            tracing::warn!("{}: schema is not available, only positional arguments are accepted", service);
+        }
+        gateway.add_client(service, client.clone(), schema, policy.clone());
+    }
+
+    let listener = TcpListener::bind(listen).await?;
+    println!("listening on http://{}", listener.local_addr()?);
+    gateway.serve(listener).await
+}
+
+// --- check-schema ---
+
+fn check_schema_files(old: &Path, new: &Path) -> anyhow::Result<Vec<SchemaChange>> {
+    let old = load_schemas(old)?;
+    let new = load_schemas(new)?;
+
+    let mut changes = vec![];
+    for old_service in old.iter() {
+        match new.iter().find(|new_service| new_service.name == old_service.name) {
+            Some(new_service) => changes.extend(check_compatibility(old_service, new_service)),
+            None => changes.push(SchemaChange {
+                compatibility: Compatibility::Breaking,
+                service: old_service.name.to_string(),
+                method: None,
+                message: "service removed".to_string(),
+            }),
+        }
+    }
+    for new_service in new
+        .iter()
+        .filter(|new_service| old.iter().all(|old_service| old_service.name != new_service.name))
+    {
+        changes.push(SchemaChange {
+            compatibility: Compatibility::Compatible,
+            service: new_service.name.to_string(),
+            method: None,
+            message: "service added".to_string(),
+        });
+    }
+
+    Ok(changes)
+}
+
+/// 读取单个服务或服务数组的描述
+fn load_schemas(path: &Path) -> anyhow::Result<Vec<ServiceSchema>> {
+    let json = std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+    match serde_json::from_str::<Vec<ServiceSchema>>(&json) {
+        Ok(schemas) => Ok(schemas),
+        Err(_) => Ok(vec![
+            ServiceSchema::from_json(&json).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?
+        ]),
+    }
+}
@@ -0,0 +1,577 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use nitrogen::{AccessPolicy, CallOptions, DynamicClient, Error, ErrorKind, Metadata, MethodSchema, Router, Service, ServiceSchema, Value};
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+/// 请求行与每个请求头的最大长度
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY: usize = 16 * 1024 * 1024;
+/// 读取一个请求（请求行、请求头与请求体）的默认时限
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// --- Gateway ---
+
+/// 以 HTTP/JSON 提供 rpc_service 的方法，供浏览器、curl 等不支持 nitrogen 协议的调用方使用
+///
+/// 每个方法对应 `POST /{Service}/{method}`，`method` 为线上名称或 `#[rpc(id = N)]` 的数字 id，
+/// 服务名中的 `<`、`,` 等字符需要百分号编码。请求体为参数的 JSON 数组（按位置），
+/// 或以参数名为键的 JSON 对象（需要服务描述，省略的参数取 `#[rpc(default)]` 的默认值）；空请求体表示没有参数。
+/// 成功时以 200 返回返回值的 JSON，方法返回 `Result` 时只返回 `Ok` 中的值。
+///
+/// 失败时响应体为 `{"error": {"kind": ..., "message": ...}}`，状态码按失败的类别区分：
+///
+/// | 状态码 | 失败 |
+/// | --- | --- |
+/// | 400 | 请求体不是 JSON，参数缺少或无法解码（`InvalidRequest`） |
+/// | 403 | 访问策略不允许匿名调用（`PermissionDenied`），或来自其他站点的请求（`Forbidden`） |
+/// | 404 | 未知的服务或方法（`UnknownService`、`UnknownMethod`） |
+/// | 415 | 请求的 `Content-Type` 不是 `application/json`（`UnsupportedMediaType`） |
+/// | 500 | 方法返回 `Err`（`kind` 为 `Application`，错误值在 `detail` 中）或服务端的其他错误 |
+/// | 502 | 与后端的连接失败、后端的响应无法解码（`Transport`、`Handshake`、`InvalidResponse`） |
+/// | 504 | 超时（`Timeout`） |
+///
+/// HTTP 调用方没有证书身份，访问策略按匿名调用方检查，只允许策略允许 [`nitrogen::Principal::any`] 调用的方法。
+/// 进程内的服务使用注册时的策略，没有策略时不检查；转发的服务以网关自己的证书身份到达服务端，
+/// 服务端的策略无法区分 HTTP 调用方，因此 [`Gateway::add_client`] 必须给出本地策略。
+///
+/// 为防止浏览器中的其他站点借用户的网络位置调用（CSRF），只接受 `Content-Type: application/json` 的请求，
+/// 带有 `Origin` 请求头时，它必须与 `Host` 相同。
+///
+/// ```ignore
+/// let mut gateway = nitrogen_extra::Gateway::new();
+/// gateway.add_router(&router);
+/// gateway.serve(TcpListener::bind("127.0.0.1:8080").await?).await?;
+///
+/// // curl localhost:8080/MyService/hello -H 'Content-Type: application/json' -d '["world"]'
+/// // curl localhost:8080/MyService/hello -H 'Content-Type: application/json' -d '{"name": "world"}'
+/// ```
+#[derive(Debug)]
+pub struct Gateway {
+    routes: HashMap<String, Route>,
+    options: CallOptions,
+    read_timeout: Duration,
+}
+
+#[derive(Debug)]
+struct Route {
+    backend: Backend,
+    schema: Option<ServiceSchema>,
+    /// 按匿名调用方检查，为空时不检查
+    policy: Option<Arc<AccessPolicy>>,
+}
+
+#[derive(Debug)]
+enum Backend {
+    /// 在进程内调用
+    Service(Service),
+    /// 通过会话转发给服务端
+    Client(DynamicClient),
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            options: CallOptions::default(),
+            read_timeout: READ_TIMEOUT,
+        }
+    }
+}
+
+impl Gateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 进程内服务的调用超时，默认为 5 秒；通过客户端转发的请求使用客户端自己的超时
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// 随每个进程内调用传递的元数据
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.options.metadata = metadata;
+        self
+    }
+
+    /// 读取一个请求的时限，默认为 30 秒；keep-alive 的连接在两个请求之间空闲也计入，超时后关闭连接
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// 在进程内调用 `service`；工厂创建的服务为每个请求单独创建实例
+    pub fn add_service(&mut self, service: Service) -> &mut Self {
+        self.add_service_with_policy(service, None)
+    }
+
+    /// 提供 `router` 中已注册的所有服务，连同它们的访问策略
+    pub fn add_router(&mut self, router: &Router) -> &mut Self {
+        for name in router.services() {
+            if let Some((service, options)) = router.lookup(name) {
+                self.add_service_with_policy(service, options.access_policy);
+            }
+        }
+        self
+    }
+
+    /// 通过 `client` 的会话转发名为 `service` 的服务，会话握手时需要请求该服务
+    ///
+    /// 转发的请求以会话的证书身份到达服务端，匿名的 HTTP 调用方只能调用 `policy` 允许 [`nitrogen::Principal::any`]
+    /// 调用的方法，如 `AccessPolicy::new().allow(Principal::any(), ["Storage/get"])`。
+    ///
+    /// 以 JSON 对象传递参数、按 `Result` 展开返回值需要服务描述，可以取生成的 `MyServiceRequest::SCHEMA`
+    /// 或通过服务端的反射服务获取。
+    pub fn add_client(&mut self, service: &str, client: DynamicClient, schema: Option<ServiceSchema>, policy: AccessPolicy) -> &mut Self {
+        let route = Route {
+            backend: Backend::Client(client),
+            schema,
+            policy: Some(Arc::new(policy)),
+        };
+        self.routes.insert(service.to_string(), route);
+        self
+    }
+
+    fn add_service_with_policy(&mut self, service: Service, policy: Option<Arc<AccessPolicy>>) -> &mut Self {
+        let name = service.name().to_string();
+        let route = Route {
+            schema: service.schema().cloned(),
+            backend: Backend::Service(service),
+            policy,
+        };
+        self.routes.insert(name, route);
+        self
+    }
+
+    /// 提供的服务名
+    pub fn services(&self) -> impl Iterator<Item = &str> + '_ {
+        self.routes.keys().map(String::as_str)
+    }
+
+    /// 接受 `listener` 上的所有连接，直到监听出错
+    pub async fn serve(self, listener: TcpListener) -> anyhow::Result<()> {
+        let gateway = Arc::new(self);
+
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let gateway = gateway.clone();
+            tokio::spawn(async move {
+                if let Err(err) = gateway.serve_connection(stream).await {
+                    tracing::debug!("Gateway::serve connection {} error: {}", addr, err);
+                }
+            });
+        }
+    }
+
+    /// 处理一个连接上的 HTTP/1.1 请求，支持 keep-alive，不支持分块编码的请求体
+    ///
+    /// 没有在时限内读完一个请求时返回 `TimedOut` 错误。
+    pub async fn serve_connection<S>(&self, stream: S) -> std::io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = BufReader::new(stream);
+
+        loop {
+            let request = tokio::time::timeout(self.read_timeout, read_request(&mut stream))
+                .await
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "Gateway::serve_connection read timeout"))??;
+            let (response, keep_alive) = match request {
+                Some(Ok(request)) => {
+                    let keep_alive = request.keep_alive;
+                    (self.handle(&request).await, keep_alive)
+                }
+                Some(Err(response)) => (response, false),
+                None => return Ok(()),
+            };
+
+            write_response(stream.get_mut(), &response, keep_alive).await?;
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let path = request.path.split('?').next().unwrap_or_default();
+        let Some((service, method)) = path.strip_prefix('/').and_then(|path| path.rsplit_once('/')) else {
+            return HttpResponse::error(404, "NotFound", format!("{} is not a method, use /{{Service}}/{{method}}", path));
+        };
+        let (Some(service), Some(method)) = (percent_decode(service), percent_decode(method)) else {
+            return HttpResponse::error(400, ErrorKind::InvalidRequest.as_str(), format!("invalid path {}", path));
+        };
+        if request.method != "POST" {
+            return HttpResponse::error(405, "MethodNotAllowed", format!("{} {} is not allowed, use POST", request.method, path)).with_allow("POST");
+        }
+        // 浏览器跨站提交的表单只能是 text/plain 等类型，JSON 请求需要预检，网关不回复预检
+        if let Some(origin) = request.origin.as_deref().filter(|origin| !is_same_origin(origin, request.host.as_deref())) {
+            return HttpResponse::error(403, "Forbidden", format!("requests from origin {} are not allowed", origin));
+        }
+        if !request.content_type.as_deref().is_some_and(is_json) {
+            return HttpResponse::error(415, "UnsupportedMediaType", "Content-Type must be application/json");
+        }
+
+        let response = self.call(&service, &method, &request.body).await;
+        tracing::debug!("Gateway::serve {}/{} {}", service, method, response.status);
+        response
+    }
+
+    async fn call(&self, service: &str, method: &str, body: &[u8]) -> HttpResponse {
+        let Some(route) = self.routes.get(service) else {
+            return HttpResponse::from_error(&Error::new(
+                ErrorKind::UnknownService,
+                format!("Gateway::call error: service {} is not provided", service),
+            ));
+        };
+
+        let method_schema = match &route.schema {
+            Some(schema) => match schema.methods.iter().find(|schema| is_method(schema, method)) {
+                Some(method_schema) => Some(method_schema),
+                None => return HttpResponse::from_error(&Error::new(ErrorKind::UnknownMethod, format!("{}::{} unknown method", service, method))),
+            },
+            None => None,
+        };
+
+        // 先检查访问策略，未授权的调用方看不到参数的校验结果
+        if let Some(policy) = &route.policy {
+            let wire_name = method_schema.map_or(method, |method_schema| method_schema.name.as_ref());
+            if !policy.is_allowed(None, service, wire_name) {
+                return HttpResponse::from_error(&Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("Gateway::call permission denied: {}/{}", service, wire_name),
+                ));
+            }
+        }
+
+        let args = match parse_args(body, method_schema) {
+            Ok(args) => args,
+            Err(message) => {
+                return HttpResponse::from_error(&Error::new(
+                    ErrorKind::InvalidRequest,
+                    format!("{}::{} invalid arguments: {}", service, method, message),
+                ))
+            }
+        };
+
+        let result = match &route.backend {
+            Backend::Service(service_impl) => service_impl.call_dynamic(method, args, &self.options).await,
+            Backend::Client(client) => client.call(service, method, args).await,
+        };
+
+        match result.and_then(|value| {
+            serde_json::to_value(value).map_err(|err| Error::new(ErrorKind::InvalidResponse, format!("{}::{} decode error: {}", service, method, err)))
+        }) {
+            Ok(value) => match method_schema.filter(|method_schema| returns_result(&method_schema.returns)) {
+                Some(_) => HttpResponse::from_result(value),
+                None => HttpResponse::ok(value),
+            },
+            Err(err) => HttpResponse::from_error(&err),
+        }
+    }
+}
+
+/// `Origin` 去掉协议后是否与 `Host` 相同；`null` 等没有协议的来源不相同
+fn is_same_origin(origin: &str, host: Option<&str>) -> bool {
+    match (origin.split_once("://"), host) {
+        (Some((_, origin_host)), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// `application/json`，可以带 `charset` 等参数
+fn is_json(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    media_type.eq_ignore_ascii_case("application/json")
+}
+
+fn is_method(schema: &MethodSchema, method: &str) -> bool {
+    schema.name == method || schema.id.is_some_and(|id| id.to_string() == method)
+}
+
+/// 返回值类型是否为 `Result<..>`、`nitrogen::Result<..>` 等
+fn returns_result(returns: &str) -> bool {
+    let base = returns.split('<').next().unwrap_or_default().trim();
+    base == "Result" || base.ends_with("::Result")
+}
+
+// --- 参数 ---
+
+/// 请求体为 JSON 数组时按位置传递，为 JSON 对象时按服务描述中的参数名排列
+fn parse_args(body: &[u8], method: Option<&MethodSchema>) -> Result<Vec<Value>, String> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(vec![]);
+    }
+
+    let args = match serde_json::from_slice::<serde_json::Value>(body).map_err(|err| format!("body is not JSON: {}", err))? {
+        serde_json::Value::Array(args) => args,
+        serde_json::Value::Object(named) => match method {
+            Some(method) => positional_args(method, named)?,
+            None => return Err("named arguments need the service schema, pass a JSON array instead".to_string()),
+        },
+        _ => return Err("body must be a JSON array or object".to_string()),
+    };
+
+    args.iter()
+        .map(|arg| nitrogen::rmpv::ext::to_value(arg).map_err(|err| err.to_string()))
+        .collect()
+}
+
+/// 省略的参数取默认值；默认值只能补在末尾，省略了某个参数时它之后的参数也要省略
+fn positional_args(method: &MethodSchema, mut named: serde_json::Map<String, serde_json::Value>) -> Result<Vec<serde_json::Value>, String> {
+    let mut args = vec![];
+    let mut omitted = None;
+
+    for arg in method.args.iter() {
+        match named.remove(arg.name.as_ref()) {
+            Some(value) => match omitted {
+                Some(omitted) => return Err(format!("argument `{}` can not be omitted when `{}` is given", omitted, arg.name)),
+                None => args.push(value),
+            },
+            None if arg.default.is_some() => {
+                omitted.get_or_insert(arg.name.as_ref());
+            }
+            None => return Err(format!("missing argument `{}`", arg.name)),
+        }
+    }
+
+    match named.keys().next() {
+        Some(name) => Err(format!("unknown argument `{}`", name)),
+        None => Ok(args),
+    }
+}
+
+/// `%XX` 解码，不把 `+` 当作空格
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+// --- HTTP ---
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    host: Option<String>,
+    origin: Option<String>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+    keep_alive: bool,
+}
+
+struct HttpResponse {
+    status: u16,
+    body: serde_json::Value,
+    allow: Option<&'static str>,
+}
+
+impl HttpResponse {
+    fn ok(body: serde_json::Value) -> Self {
+        Self {
+            status: 200,
+            body,
+            allow: None,
+        }
+    }
+
+    fn error(status: u16, kind: &str, message: impl Into<String>) -> Self {
+        let body = json!({ "error": { "kind": kind, "message": message.into() } });
+        Self { status, body, allow: None }
+    }
+
+    fn from_error(err: &Error) -> Self {
+        let status = match err.kind() {
+            ErrorKind::InvalidRequest => 400,
+            ErrorKind::PermissionDenied => 403,
+            ErrorKind::UnknownService | ErrorKind::UnknownMethod => 404,
+            ErrorKind::Transport | ErrorKind::Handshake | ErrorKind::InvalidResponse => 502,
+            ErrorKind::Timeout => 504,
+            _ => 500,
+        };
+        Self::error(status, err.kind().as_str(), err.message.as_str())
+    }
+
+    /// 方法返回 `Result` 时，`Ok` 的值作为响应体，`Err` 的值以 500 返回
+    fn from_result(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Object(mut map) if map.len() == 1 => match (map.remove("Ok"), map.remove("Err")) {
+                (Some(value), _) => Self::ok(value),
+                (_, Some(detail)) => {
+                    let body = json!({ "error": { "kind": "Application", "message": detail.to_string(), "detail": detail } });
+                    Self {
+                        status: 500,
+                        body,
+                        allow: None,
+                    }
+                }
+                _ => Self::ok(serde_json::Value::Object(map)),
+            },
+            value => Self::ok(value),
+        }
+    }
+
+    fn with_allow(mut self, allow: &'static str) -> Self {
+        self.allow = Some(allow);
+        self
+    }
+}
+
+/// 读取一个请求；连接在请求开始前关闭时返回 `None`，请求不合法时返回要回复的错误
+async fn read_request<S>(stream: &mut BufReader<S>) -> std::io::Result<Option<Result<HttpRequest, HttpResponse>>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let Some(line) = read_line(stream).await? else {
+        return Ok(None);
+    };
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path), Some(version)) = (parts.next(), parts.next(), parts.next()) else {
+        return Ok(Some(Err(HttpResponse::error(400, "BadRequest", format!("invalid request line {:?}", line)))));
+    };
+    let (method, path, version) = (method.to_string(), path.to_string(), version.to_string());
+
+    let mut content_length = 0;
+    let (mut host, mut origin, mut content_type) = (None, None, None);
+    let mut keep_alive = version == "HTTP/1.1";
+    let mut expect_continue = false;
+    for index in 0.. {
+        let Some(line) = read_line(stream).await? else {
+            return Ok(None);
+        };
+        if line.is_empty() {
+            break;
+        }
+        if index == MAX_HEADERS {
+            return Ok(Some(Err(HttpResponse::error(431, "BadRequest", "too many headers"))));
+        }
+
+        let Some((name, value)) = line.split_once(':') else {
+            return Ok(Some(Err(HttpResponse::error(400, "BadRequest", format!("invalid header {:?}", line)))));
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => match value.parse::<usize>() {
+                Ok(length) => content_length = length,
+                Err(_) => return Ok(Some(Err(HttpResponse::error(400, "BadRequest", format!("invalid content-length {:?}", value))))),
+            },
+            "transfer-encoding" => {
+                return Ok(Some(Err(HttpResponse::error(
+                    501,
+                    "NotImplemented",
+                    "chunked request bodies are not supported",
+                ))))
+            }
+            "connection" => keep_alive = !value.eq_ignore_ascii_case("close") && (keep_alive || value.eq_ignore_ascii_case("keep-alive")),
+            "expect" => expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            "host" => host = Some(value.to_string()),
+            "origin" => origin = Some(value.to_string()),
+            "content-type" => content_type = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    if content_length > MAX_BODY {
+        return Ok(Some(Err(HttpResponse::error(
+            413,
+            "BadRequest",
+            format!("request body is larger than {} bytes", MAX_BODY),
+        ))));
+    }
+    if expect_continue && content_length > 0 {
+        stream.get_mut().write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+        stream.get_mut().flush().await?;
+    }
+
+    // 随读到的数据增长，不按请求头预先分配
+    let mut body = Vec::new();
+    if (&mut *stream).take(content_length as u64).read_to_end(&mut body).await? < content_length {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "request body is shorter than content-length",
+        ));
+    }
+
+    Ok(Some(Ok(HttpRequest {
+        method,
+        path,
+        host,
+        origin,
+        content_type,
+        body,
+        keep_alive,
+    })))
+}
+
+/// 读取一行并去掉行尾；连接关闭时返回 `None`，超过 [`MAX_LINE`] 时返回错误
+async fn read_line<S>(stream: &mut BufReader<S>) -> std::io::Result<Option<String>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut line = String::new();
+    if (&mut *stream).take(MAX_LINE).read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "request line or header is too long"));
+    }
+
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+async fn write_response<S>(stream: &mut S, response: &HttpResponse, keep_alive: bool) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let body = response.body.to_string();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+        response.status,
+        reason_phrase(response.status),
+        body.len()
+    );
+    if let Some(allow) = response.allow {
+        head.push_str(&format!("Allow: {}\r\n", allow));
+    }
+    if !keep_alive {
+        head.push_str("Connection: close\r\n");
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.flush().await
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
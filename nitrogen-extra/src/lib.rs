@@ -1,5 +1,6 @@
 mod discovery;
+mod gateway;
 mod relay;
 mod speed_testing;
 
-pub use {discovery::*, relay::*, speed_testing::*};
+pub use {discovery::*, gateway::*, relay::*, speed_testing::*};
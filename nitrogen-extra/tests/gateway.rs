@@ -0,0 +1,214 @@
+//! 经过 Gateway 的 HTTP 调用：状态码、keep-alive 与跨站请求
+
+use std::time::Duration;
+
+use nitrogen::{AccessPolicy, CodecConfig, DynamicClient, Principal, Router, Session};
+use nitrogen_extra::Gateway;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+#[nitrogen::rpc_service]
+pub trait Calc {
+    async fn add(&self, a: i64, b: i64) -> i64;
+    async fn div(&self, a: i64, b: i64) -> Result<i64, String>;
+    async fn sleep(&self, millis: u64);
+}
+
+pub struct CalcImpl;
+
+#[nitrogen::async_trait]
+impl Calc for CalcImpl {
+    async fn add(&self, a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    async fn div(&self, a: i64, b: i64) -> Result<i64, String> {
+        a.checked_div(b).ok_or_else(|| "division by zero".to_string())
+    }
+
+    async fn sleep(&self, millis: u64) {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+    }
+}
+
+fn gateway() -> Gateway {
+    let mut gateway = Gateway::new().with_timeout(Duration::from_millis(100));
+    gateway.add_service(CalcImpl.into_service());
+    gateway
+}
+
+/// 在 duplex 上运行 `gateway`，返回客户端一侧与连接任务
+fn connect(gateway: Gateway) -> (BufReader<DuplexStream>, tokio::task::JoinHandle<std::io::Result<()>>) {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(async move { gateway.serve_connection(server_io).await });
+    (BufReader::new(client_io), task)
+}
+
+async fn post(io: &mut BufReader<DuplexStream>, path: &str, body: &str, close: bool) -> (u16, Value) {
+    let connection = if close { "Connection: close\r\n" } else { "" };
+    post_with_headers(io, path, body, &format!("Content-Type: application/json\r\n{}", connection)).await
+}
+
+/// `headers` 中的每一行以 `\r\n` 结尾
+async fn post_with_headers(io: &mut BufReader<DuplexStream>, path: &str, body: &str, headers: &str) -> (u16, Value) {
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: {}\r\n{}\r\n{}",
+        path,
+        body.len(),
+        headers,
+        body
+    );
+    io.get_mut().write_all(request.as_bytes()).await.unwrap();
+    read_response(io).await
+}
+
+async fn read_response<S: AsyncRead + Unpin>(io: &mut BufReader<S>) -> (u16, Value) {
+    let mut line = String::new();
+    io.read_line(&mut line).await.unwrap();
+    let status = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        io.read_line(&mut line).await.unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length: ") {
+            content_length = value.parse().unwrap();
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    io.read_exact(&mut body).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn maps_results_to_statuses() {
+    let (mut io, _task) = connect(gateway());
+
+    assert_eq!(post(&mut io, "/Calc/add", "[1, 2]", false).await, (200, json!(3)));
+    assert_eq!(post(&mut io, "/Calc/add", r#"{"a": 2, "b": 3}"#, false).await, (200, json!(5)));
+    assert_eq!(post(&mut io, "/Calc/div", "[6, 3]", false).await, (200, json!(2)));
+
+    let cases = [
+        ("/Calc/add", "[1,", 400, "InvalidRequest"),
+        ("/Calc/add", r#"{"a": 1}"#, 400, "InvalidRequest"),
+        ("/Calc/sub", "[1, 2]", 404, "UnknownMethod"),
+        ("/Other/add", "[1, 2]", 404, "UnknownService"),
+        ("/Calc/div", "[1, 0]", 500, "Application"),
+        ("/Calc/sleep", "[1000]", 504, "Timeout"),
+    ];
+    for (path, body, status, kind) in cases {
+        let response = post(&mut io, path, body, false).await;
+        assert_eq!(response.0, status, "{} {} {}", path, body, response.1);
+        assert_eq!(response.1["error"]["kind"], kind, "{} {}", path, body);
+    }
+}
+
+#[tokio::test]
+async fn denies_methods_not_allowed_to_anyone() {
+    let mut router = Router::new();
+    router.add(CalcImpl.into_service()).set_access_policy(
+        AccessPolicy::new()
+            .allow(Principal::any(), ["Calc/add"])
+            .allow(Principal::cn("billing"), ["Calc/*"]),
+    );
+    let mut gateway = Gateway::new();
+    gateway.add_router(&router);
+
+    let (mut io, _task) = connect(gateway);
+    assert_eq!(post(&mut io, "/Calc/add", "[1, 2]", false).await, (200, json!(3)));
+
+    let (status, body) = post(&mut io, "/Calc/div", "[4, 2]", false).await;
+    assert_eq!(status, 403, "{}", body);
+    assert_eq!(body["error"]["kind"], "PermissionDenied");
+}
+
+#[tokio::test]
+async fn reports_unreachable_backend() {
+    // 后端在握手前就关闭了连接
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    drop(server_io);
+    let session = Session::new(client_io, &["Calc"], CodecConfig::default());
+    let mut gateway = Gateway::new();
+    gateway.add_client(
+        "Calc",
+        DynamicClient::with_session(&session),
+        None,
+        AccessPolicy::new().allow(Principal::any(), ["Calc/*"]),
+    );
+
+    let (mut io, _task) = connect(gateway);
+    let (status, body) = post(&mut io, "/Calc/add", "[1, 2]", false).await;
+    assert_eq!(status, 502, "{}", body);
+}
+
+#[tokio::test]
+async fn forwards_only_methods_allowed_by_local_policy() {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(CalcImpl.serve(server_io));
+    let session = Session::new(client_io, &["Calc"], CodecConfig::default());
+    let mut gateway = Gateway::new();
+    let policy = AccessPolicy::new().allow(Principal::any(), ["Calc/add"]);
+    gateway.add_client("Calc", DynamicClient::with_session(&session), Some(CalcRequest::SCHEMA), policy);
+
+    let (mut io, _task) = connect(gateway);
+    assert_eq!(post(&mut io, "/Calc/add", "[1, 2]", false).await, (200, json!(3)));
+    // 服务端没有访问策略，但转发的请求带着网关的身份，由网关按本地策略拒绝
+    let (status, body) = post(&mut io, "/Calc/div", "[4, 2]", false).await;
+    assert_eq!(status, 403, "{}", body);
+    assert_eq!(body["error"]["kind"], "PermissionDenied");
+}
+
+#[tokio::test]
+async fn refuses_cross_site_requests() {
+    let (mut io, _task) = connect(gateway());
+
+    let cases = [
+        ("", 415, "UnsupportedMediaType"),
+        ("Content-Type: text/plain\r\n", 415, "UnsupportedMediaType"),
+        ("Content-Type: application/x-www-form-urlencoded\r\n", 415, "UnsupportedMediaType"),
+        ("Content-Type: application/json\r\nOrigin: https://evil.example\r\n", 403, "Forbidden"),
+        ("Content-Type: application/json\r\nOrigin: null\r\n", 403, "Forbidden"),
+    ];
+    for (headers, status, kind) in cases {
+        let (response_status, body) = post_with_headers(&mut io, "/Calc/add", "[1, 2]", headers).await;
+        assert_eq!(response_status, status, "{:?} {}", headers, body);
+        assert_eq!(body["error"]["kind"], kind, "{:?}", headers);
+    }
+
+    // 同源请求与带参数的 JSON 类型可以调用
+    let headers = "Content-Type: application/json; charset=utf-8\r\nOrigin: http://localhost:8080\r\n";
+    assert_eq!(post_with_headers(&mut io, "/Calc/add", "[1, 2]", headers).await, (200, json!(3)));
+}
+
+#[tokio::test]
+async fn keeps_connection_alive_until_close() {
+    let (mut io, task) = connect(gateway());
+
+    for i in 0..3 {
+        assert_eq!(post(&mut io, "/Calc/add", &format!("[{}, 1]", i), false).await, (200, json!(i + 1)));
+    }
+    assert_eq!(post(&mut io, "/Calc/add", "[1, 1]", true).await, (200, json!(2)));
+
+    // `Connection: close` 之后服务端结束连接
+    task.await.unwrap().unwrap();
+    let mut rest = vec![];
+    assert_eq!(io.read_to_end(&mut rest).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn closes_slow_connections() {
+    let (mut io, task) = connect(gateway().with_read_timeout(Duration::from_millis(50)));
+
+    // 请求体始终没有发完
+    io.get_mut()
+        .write_all(b"POST /Calc/add HTTP/1.1\r\nContent-Length: 100\r\n\r\n[1,")
+        .await
+        .unwrap();
+    let err = task.await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}
@@ -2,6 +2,40 @@ use proc_macro::TokenStream;
 
 mod rpc;
 
+/// 为 trait 生成请求/响应枚举、服务端扩展 `*Ext` 与客户端 `*Client`
+///
+/// 服务端可以直接 `serve(stream)`，也可以通过 `into_service()` 注册到 `nitrogen::Router`。
+/// `MyServiceExt` 上还会生成 `NAME` 与 `SCHEMA` 常量，可以通过实现类型访问，如 `MyServiceImpl::NAME`。
+///
+/// trait 只能包含接收 `&self` 的 `async fn` 方法与关联类型，方法不能有类型、常量或生命周期参数；
+/// 不满足时在对应的源码位置报告编译错误。各项功能的用法与兼容性规则见仓库的 README。
+///
+/// # 属性选项
+///
+/// ```ignore
+/// #[nitrogen::rpc_service(
+///     name = "acme.Storage.v2",  // 线上服务名，用于 NAME、握手与 Router 分发，默认为 trait 名
+///     client = "StorageRpc",     // 客户端类型名，默认为 `StorageClient`
+///     request = "StorageReq",    // 请求枚举名，默认为 `StorageRequest`
+///     response = "StorageResp",  // 响应枚举名，默认为 `StorageResponse`
+///     derive(PartialEq, Hash),   // 额外添加到请求与响应枚举上的 derive
+///     crate = "::my_reexport::nitrogen", // 通过重新导出的 crate 使用时，生成代码引用 nitrogen 的路径，默认为 `::nitrogen`
+///     mock,                      // 生成测试替身 `MockStorage`
+///     native,                    // 保留原生 async fn，实现时不需要 `#[nitrogen::async_trait]`
+///     typed_name,                // 泛型服务的服务名由请求枚举的 `nitrogen::TypeName` 给出
+/// )]
+/// pub trait Storage { ... }
+/// ```
+///
+/// # 方法与参数选项
+///
+/// - `#[rpc(name = "...")]`：方法的线上名称，默认为方法名。
+/// - `#[rpc(id = N)]`：方法的数字 id，指定后请求发送 id 而不是名称。
+/// - 参数上的 `#[rpc(default)]` 或 `#[rpc(default = expr)]`：旧客户端缺少该参数时的取值，新增参数只能加在末尾。
+///
+/// `&nitrogen::Context`（或 `crate` 选项给出的路径下的 `Context`）参数由服务端填入当前请求的上下文，不在线上传输；
+/// 只按完整路径识别，`&Context` 是普通参数。
+/// 其他参数可以是引用，请求中保存拥有所有权的形式。
 #[proc_macro_attribute]
 pub fn rpc_service(attr: TokenStream, input: TokenStream) -> TokenStream {
     rpc::rpc_service(attr, input)
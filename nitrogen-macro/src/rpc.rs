@@ -1,42 +1,79 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, ItemEnum, ItemTrait};
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, ItemTrait};
 
 /// #[rpc_service]
 /// pub trait MyService {
 ///     async fn fn_name(&self, arg1: Arg1, arg2: Arg2, arg3: Arg3) -> Return;
 ///     async fn fn_name2(&self);
 /// }
-pub fn rpc_service(_attr: TokenStream, input: TokenStream) -> TokenStream {
+pub fn rpc_service(attr: TokenStream, input: TokenStream) -> TokenStream {
     let mut input = parse_macro_input!(input as ItemTrait);
 
+    let mut attrs = ServiceAttrs::default();
+    let attr_parser = syn::meta::parser(|meta| attrs.parse(meta));
+    parse_macro_input!(attr with attr_parser);
+
+    if let Err(err) = check_trait(&input, &attrs) {
+        return TokenStream::from(err.to_compile_error());
+    }
+
     set_supertraits(&mut input);
 
-    let request_enum = make_request_enum(&input);
-    let response_enum = make_response_enum(&input);
+    // 请求、响应与客户端中的类型不能引用 `Self`，关联类型换成同名的类型参数
+    let wire = make_wire_trait(&input);
+
+    let schema = make_schema(&wire, &attrs);
+    let request_enum = make_request_enum(&wire, &attrs);
+    let request_serde = make_request_serde(&wire, &attrs);
+    let response_enum = make_response_enum(&wire, &attrs);
+    let response_serde = make_response_serde(&wire, &attrs);
+    let dynamic_impls = make_dynamic_impls(&wire, &attrs);
 
-    let ext_trait = make_ext_trait(&input);
-    let ext_impl = make_ext_impl(&input);
+    let ext_trait = make_ext_trait(&input, &attrs);
+    let ext_impl = make_ext_impl(&input, &attrs);
+    let arc_impl = make_arc_impl(&input, &attrs);
 
-    let client_struct = make_client_struct(&input);
-    let client_impl_new = make_client_impl_new(&input);
-    let client_impl_trait = make_client_impl_trait(&input);
-    let client_impl_fn = make_client_impl_fn(&input);
+    let client_struct = make_client_struct(&wire, &attrs);
+    let client_impl_new = make_client_impl_new(&wire, &attrs);
+    let client_impl_trait = make_client_impl_trait(&wire, &attrs);
+    let client_impl_fn = make_client_impl_fn(&wire, &attrs);
+
+    let mock = attrs.mock.then(|| make_mock(&wire, &attrs));
+
+    strip_rpc_attrs(&mut input);
+
+    let krate = &attrs.krate;
+    let input = if attrs.native {
+        make_native_fns(&mut input);
+        quote!( #input )
+    } else {
+        quote!(
+            #[#krate::__private::async_trait]
+            #input
+        )
+    };
 
     let output = quote!(
-        #[async_trait::async_trait]
         #input
 
         #request_enum
+        #request_serde
+        #schema
         #response_enum
+        #response_serde
+        #dynamic_impls
 
         #ext_trait
         #ext_impl
+        #arc_impl
 
         #client_struct
         #client_impl_new
         #client_impl_trait
         #client_impl_fn
+
+        #mock
     );
 
     TokenStream::from(output)
@@ -44,46 +81,248 @@ pub fn rpc_service(_attr: TokenStream, input: TokenStream) -> TokenStream {
 
 // --- 设置基础特征 ---
 
+/// 服务端通过 `Arc` 共享实现，不要求 `Clone`；trait 上不生成常量，未指定 `native` 时可以用作 `dyn MyService`
+///
 /// #[async_trait::async_trait]
-/// pub trait MyService: Clone + Send + Sync + 'static {
-///     const NAME: &'static str = "MyService";
+/// pub trait MyService: Send + Sync + 'static {
 ///     async fn fn_name(&self, arg1: Arg1, arg2: Arg2, arg3: Arg3) -> Return;
 ///     async fn fn_name2(&self);
 /// }
 fn set_supertraits(input: &mut ItemTrait) {
-    input.supertraits.push(syn::parse_quote!(Clone));
     input.supertraits.push(syn::parse_quote!(Send));
     input.supertraits.push(syn::parse_quote!(Sync));
     input.supertraits.push(syn::parse_quote!('static));
+}
+
+/// 共享的实现，如 `Arc<MyServiceImpl>` 或 `Arc<dyn MyService>`，也可以直接作为服务
+///
+/// #[async_trait::async_trait]
+/// impl<T> MyService for std::sync::Arc<T>
+/// where
+///     T: MyService + ?Sized,
+/// {
+///     async fn fn_name(&self, arg1: Arg1, arg2: Arg2, arg3: Arg3) -> Return {
+///         T::fn_name(self, arg1, arg2, arg3).await
+///     }
+/// }
+///
+/// 泛型 trait 带上 trait 的类型参数与约束，关联类型取自 `T`：
+///
+/// impl<K, V, T> KvStore<K, V> for std::sync::Arc<T>
+/// where
+///     T: KvStore<K, V> + ?Sized,
+///     K: Send + Sync + 'static,
+///     V: Send + Sync + 'static,
+///     <T as KvStore<K, V>>::Cursor: Send + Sync + 'static,
+/// {
+///     type Cursor = <T as KvStore<K, V>>::Cursor;
+///     ...
+/// }
+fn make_arc_impl(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let generics = ServiceGenerics::new(input);
+    let t = generics.fresh("T");
+    let trait_path = generics.trait_path();
+    let params = &generics.params;
+    let predicates = &generics.predicates;
+
+    let assoc_types = generics.assoc_types.iter().map(|assoc| quote!( type #assoc = <#t as #trait_path>::#assoc; ));
+
+    let fns = input.items.iter().filter_map(|item| match item {
+        syn::TraitItem::Fn(item_fn) => Some(item_fn),
+        _ => None,
+    });
+    let forward_fns = fns.map(|item_fn| {
+        let fn_ident = &item_fn.sig.ident;
+        let fn_sig_inputs = make_sig_inputs(item_fn);
+        let fn_args = fn_sig_inputs.iter().filter_map(|fn_input| match fn_input {
+            syn::FnArg::Receiver(_receiver) => None,
+            syn::FnArg::Typed(pat_type) => Some(&pat_type.pat),
+        });
+        let output = &item_fn.sig.output;
+        quote!(
+            async fn #fn_ident(#(#fn_sig_inputs),*) #output {
+                #t::#fn_ident(self, #(#fn_args),*).await
+            }
+        )
+    });
+
+    let async_trait = (!attrs.native).then(|| quote!( #[#krate::__private::async_trait] ));
+    // 转发的 future 持有参数，类型参数与关联类型需要能在任务间传递
+    let param_bounds = ServiceGenerics::bounds(&generics.args_for(&t.to_token_stream()), quote!(Send + Sync + 'static));
+
+    quote!(
+        #async_trait
+        impl<#(#params,)* #t> #trait_path for ::std::sync::Arc<#t>
+        where
+            #t: #trait_path + ?Sized,
+            #(#predicates,)*
+            #param_bounds
+        {
+            #(#assoc_types)*
+            #(#forward_fns)*
+        }
+    )
+}
+
+// --- 生成服务描述 ---
+
+/// impl MyServiceRequest {
+///     pub const SCHEMA: nitrogen::ServiceSchema = nitrogen::ServiceSchema {
+///         name: std::borrow::Cow::Borrowed("MyService"),
+///         docs: std::borrow::Cow::Borrowed("..."),
+///         methods: std::borrow::Cow::Borrowed(&[
+///             nitrogen::MethodSchema {
+///                 name: std::borrow::Cow::Borrowed("fn_name"),
+///                 rust_name: std::borrow::Cow::Borrowed("fn_name"),
+///                 id: None,
+///                 docs: std::borrow::Cow::Borrowed("..."),
+///                 args: std::borrow::Cow::Borrowed(&[
+///                     nitrogen::ArgSchema { name: std::borrow::Cow::Borrowed("arg1"), ty: std::borrow::Cow::Borrowed("Arg1"), default: None },
+///                     ...
+///                 ]),
+///                 returns: std::borrow::Cow::Borrowed("Return"),
+///             },
+///             ...
+///         ]),
+///     };
+/// }
+///
+/// 泛型 trait 的描述中类型参数按名称出现，如 `ty: "K"`；
+/// 指定 `typed_name` 时还生成带有类型实例化的服务名，取自用户为每个实例化实现的 `TypeName`：
+///
+/// impl<K, V> KvStoreRequest<K, V>
+/// where
+///     Self: nitrogen::TypeName,
+/// {
+///     pub fn service_name() -> &'static str {
+///         <Self as nitrogen::TypeName>::TYPE_NAME
+///     }
+/// }
+fn make_schema(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let name = attrs.service_name(input);
+
+    let methods = input.items.iter().filter_map(|item| {
+        if let syn::TraitItem::Fn(item_fn) = item {
+            let method_attrs = parse_method_attrs(item_fn).unwrap_or_default();
+            let rust_name = item_fn.sig.ident.to_string();
+            let wire_name = method_attrs.name.unwrap_or_else(|| rust_name.clone());
+            let id = match method_attrs.id {
+                Some(id) => quote!( Some(#id) ),
+                None => quote!(None),
+            };
+            let docs = parse_docs(&item_fn.attrs);
+
+            let args = wire_args(item_fn, &attrs.krate).map(|pat_type| {
+                let arg_name = match &*pat_type.pat {
+                    syn::Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                    pat => type_to_string(pat),
+                };
+                let ty = type_to_string(&owned_type(&pat_type.ty));
+                let default = match parse_arg_default(pat_type).unwrap_or_default() {
+                    Some(expr) => {
+                        let expr = type_to_string(&expr);
+                        quote!( Some(std::borrow::Cow::Borrowed(#expr)) )
+                    }
+                    None => quote!(None),
+                };
+                quote!(
+                    #krate::ArgSchema {
+                        name: std::borrow::Cow::Borrowed(#arg_name),
+                        ty: std::borrow::Cow::Borrowed(#ty),
+                        default: #default,
+                    }
+                )
+            });
+
+            let returns = match &item_fn.sig.output {
+                syn::ReturnType::Default => "()".to_string(),
+                syn::ReturnType::Type(_, ty) => type_to_string(ty),
+            };
+
+            Some(quote!(
+                #krate::MethodSchema {
+                    name: std::borrow::Cow::Borrowed(#wire_name),
+                    rust_name: std::borrow::Cow::Borrowed(#rust_name),
+                    id: #id,
+                    docs: std::borrow::Cow::Borrowed(#docs),
+                    args: std::borrow::Cow::Borrowed(&[#(#args),*]),
+                    returns: std::borrow::Cow::Borrowed(#returns),
+                }
+            ))
+        } else {
+            None
+        }
+    });
+
+    let docs = parse_docs(&input.attrs);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let service_name = attrs.typed_name.then(|| {
+        quote!(
+            impl<#(#params),*> #request_enum_ident #ty_args
+            where
+                Self: #krate::TypeName,
+            {
+                /// 带有类型实例化的服务名，如 `KvStore<String, u32>`，用于握手与 `nitrogen::Router` 分发
+                pub fn service_name() -> &'static str {
+                    <Self as #krate::TypeName>::TYPE_NAME
+                }
+            }
+        )
+    });
+
+    let output = quote!(
+        impl<#(#params),*> #request_enum_ident #ty_args {
+            /// 服务的描述，可以通过 `to_json` 导出
+            pub const SCHEMA: #krate::ServiceSchema = #krate::ServiceSchema {
+                name: std::borrow::Cow::Borrowed(#name),
+                docs: std::borrow::Cow::Borrowed(#docs),
+                methods: std::borrow::Cow::Borrowed(&[#(#methods),*]),
+            };
+        }
 
-    let name = input.ident.to_string();
-    input.items.push(syn::parse_quote!(const NAME: &'static str = #name;));
+        #service_name
+    );
+
+    output
 }
 
 // --- 生成 request 和 response 枚举 ---
 
-/// #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// 引用参数保存为拥有所有权的形式，见 [`owned_type`]
+///
+/// #[derive(Debug, Clone)]
 /// pub enum MyServiceRequest {
 ///     FnName(Arg1, Arg2, Arg3),
 ///     FnName2,
+///     #[doc(hidden)]
+///     __Unknown(nitrogen::MethodKey),
 /// }
-fn make_request_enum(input: &ItemTrait) -> ItemEnum {
-    let request_enum_ident = make_request_enum_ident(input);
+///
+/// 泛型 trait 依次以 trait 的类型参数与关联类型为类型参数，并带有一个不会构造的变体，
+/// 使没有出现在参数中的类型参数也被使用：
+///
+/// pub enum KvStoreRequest<K, V, Cursor> {
+///     Get(K),
+///     ...
+///     #[doc(hidden)]
+///     __Phantom(std::convert::Infallible, std::marker::PhantomData<fn() -> (K, V, Cursor)>),
+/// }
+fn make_request_enum(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let request_enum_ident = make_request_enum_ident(input, attrs);
 
     let request_enum_items = input.items.iter().filter_map(|item| {
         if let syn::TraitItem::Fn(item_fn) = item {
             let item_ty_ident = to_camel_case(&format!("{}", item_fn.sig.ident));
             let request_item_ident = syn::Ident::new(&item_ty_ident, item_fn.sig.ident.span());
 
-            let fn_inputs = item_fn
-                .sig
-                .inputs
-                .iter()
-                .filter_map(|fn_input| match fn_input {
-                    syn::FnArg::Receiver(_receiver) => None,
-                    syn::FnArg::Typed(pat_type) => Some(pat_type.ty.clone()),
-                })
-                .collect::<Vec<_>>();
+            let fn_inputs = make_arg_types(item_fn, &attrs.krate);
 
             let output = if fn_inputs.is_empty() {
                 quote!( #request_item_ident )
@@ -96,21 +335,268 @@ fn make_request_enum(input: &ItemTrait) -> ItemEnum {
         }
     });
 
-    let request_enum = quote!(
-        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-        pub enum #request_enum_ident { #(#request_enum_items),* }
+    let generics = ServiceGenerics::new(input);
+    let ty_args = generics.ty_args();
+    let phantom = generics.phantom_variant();
+
+    let derives = &attrs.derives;
+    let output = quote!(
+        #[derive(Debug, Clone, #(#derives),*)]
+        pub enum #request_enum_ident #ty_args {
+            #(#request_enum_items,)*
+            #[doc(hidden)]
+            __Unknown(#krate::MethodKey),
+            #phantom
+        }
     );
 
-    syn::parse(TokenStream::from(request_enum)).unwrap()
+    output
+}
+
+/// 请求在线上编码为 `[method_key, [arg1, arg2, ...]]`
+///
+/// impl serde::Serialize for MyServiceRequest {
+///     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+///         let mut tuple = serializer.serialize_tuple(2)?;
+///         match self {
+///             MyServiceRequest::FnName(arg0, arg1, arg2) => {
+///                 tuple.serialize_element(&nitrogen::MethodKey::name("fn_name"))?;
+///                 tuple.serialize_element(&(arg0, arg1, arg2))?;
+///             }
+///             ...
+///         }
+///         tuple.end()
+///     }
+/// }
+///
+/// impl<'de> serde::Deserialize<'de> for MyServiceRequest {
+///     // 按 method_key 选择变体；未知方法解码为 __Unknown，
+///     // 缺少的末尾参数取 #[rpc(default)] 给出的默认值，多余的末尾参数被忽略并记录警告
+/// }
+///
+/// 泛型 trait 的类型参数分别要求 `Serialize` 与 `DeserializeOwned`；
+/// 函数内定义的 `Visitor`、`Args` 不能使用外层的类型参数，各自带上同样的类型参数
+fn make_request_serde(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let name = attrs.service_name(input);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let serialize_bounds = ServiceGenerics::bounds(&params, quote!( #krate::__private::serde::Serialize ));
+    let deserialize_bounds = ServiceGenerics::bounds(&params, quote!( #krate::__private::serde::de::DeserializeOwned ));
+    let phantom = generics.phantom();
+    let marker = phantom.as_ref().map(|_| quote!(::core::marker::PhantomData));
+    let turbofish = ServiceGenerics::turbofish(&params);
+    let s = generics.fresh("S");
+    let d = generics.fresh("D");
+    let a = generics.fresh("A");
+    let local_struct = |ident: syn::Ident| match &phantom {
+        Some(phantom) => (quote!( struct #ident #ty_args(#phantom); ), quote!( #ident(::core::marker::PhantomData) )),
+        None => (quote!( struct #ident; ), quote!( #ident )),
+    };
+
+    let fns = input
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::TraitItem::Fn(item_fn) => Some(item_fn),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let serialize_arms = fns.iter().map(|item_fn| {
+        let enum_item_ident = syn::Ident::new(&to_camel_case(&format!("{}", item_fn.sig.ident)), item_fn.sig.ident.span());
+        let method_key = make_method_key(item_fn, attrs);
+        let arg_idents = make_arg_idents(item_fn, &attrs.krate);
+
+        if arg_idents.is_empty() {
+            quote!(
+                #request_enum_ident::#enum_item_ident => {
+                    tuple.serialize_element(&#method_key)?;
+                    tuple.serialize_element(&[(); 0])?;
+                }
+            )
+        } else {
+            quote!(
+                #request_enum_ident::#enum_item_ident(#(#arg_idents),*) => {
+                    tuple.serialize_element(&#method_key)?;
+                    tuple.serialize_element(&(#(#arg_idents,)*))?;
+                }
+            )
+        }
+    });
+
+    let deserialize_arms = fns.iter().map(|item_fn| {
+        let enum_item_ident = syn::Ident::new(&to_camel_case(&format!("{}", item_fn.sig.ident)), item_fn.sig.ident.span());
+        let method_matcher = make_method_matcher(item_fn);
+        let arg_idents = make_arg_idents(item_fn, &attrs.krate);
+
+        let wire_name = parse_method_attrs(item_fn)
+            .unwrap_or_default()
+            .name
+            .unwrap_or_else(|| item_fn.sig.ident.to_string());
+
+        if arg_idents.is_empty() {
+            return quote!(
+                key if #method_matcher => {
+                    if let Some(args) = seq.next_element::<::std::vec::Vec<#krate::__private::serde::de::IgnoredAny>>()? {
+                        #krate::ignore_trailing_args(#name, #wire_name, args.len());
+                    }
+                    #request_enum_ident::#enum_item_ident
+                }
+            );
+        }
+
+        let arg_count = arg_idents.len();
+        let expecting = format!("{} arguments of {}", arg_count, item_fn.sig.ident);
+        let arg_types = make_arg_types(item_fn, &attrs.krate);
+
+        let arg_reads = wire_args(item_fn, &attrs.krate)
+            .zip(arg_idents.iter())
+            .enumerate()
+            .map(|(index, (pat_type, arg_ident))| match parse_arg_default(pat_type).ok().flatten() {
+                Some(default) => quote!(
+                    let #arg_ident = match seq.next_element()? {
+                        Some(value) => value,
+                        None => #default,
+                    };
+                ),
+                None => quote!(
+                    let #arg_ident = seq.next_element()?.ok_or_else(|| #krate::__private::serde::de::Error::invalid_length(#index, &self))?;
+                ),
+            });
+        let (args_visitor_struct, args_visitor) = local_struct(syn::Ident::new("ArgsVisitor", proc_macro2::Span::call_site()));
+
+        quote!(
+            key if #method_matcher => {
+                struct Args #ty_args(#(#arg_types,)* #phantom);
+
+                impl<'de, #(#params),*> #krate::__private::serde::Deserialize<'de> for Args #ty_args
+                where
+                    #deserialize_bounds
+                {
+                    fn deserialize<#d>(deserializer: #d) -> std::result::Result<Self, #d::Error>
+                    where
+                        #d: #krate::__private::serde::Deserializer<'de>,
+                    {
+                        #args_visitor_struct
+
+                        impl<'de, #(#params),*> #krate::__private::serde::de::Visitor<'de> for ArgsVisitor #ty_args
+                        where
+                            #deserialize_bounds
+                        {
+                            type Value = Args #ty_args;
+
+                            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                                f.write_str(#expecting)
+                            }
+
+                            fn visit_seq<#a>(self, mut seq: #a) -> std::result::Result<Self::Value, #a::Error>
+                            where
+                                #a: #krate::__private::serde::de::SeqAccess<'de>,
+                            {
+                                #(#arg_reads)*
+                                let mut ignored = 0;
+                                while seq.next_element::<#krate::__private::serde::de::IgnoredAny>()?.is_some() {
+                                    ignored += 1;
+                                }
+                                #krate::ignore_trailing_args(#name, #wire_name, ignored);
+                                Ok(Args(#(#arg_idents,)* #marker))
+                            }
+                        }
+
+                        deserializer.deserialize_seq(#args_visitor)
+                    }
+                }
+
+                let Args #turbofish(#(#arg_idents,)* ..) = seq.next_element()?.ok_or_else(|| #krate::__private::serde::de::Error::invalid_length(1, &self))?;
+                #request_enum_ident::#enum_item_ident(#(#arg_idents),*)
+            }
+        )
+    });
+
+    let expecting = format!("{}", request_enum_ident);
+    let phantom_arm = generics.phantom_arm(&request_enum_ident, quote!(*never));
+    let (visitor_struct, visitor) = local_struct(syn::Ident::new("Visitor", proc_macro2::Span::call_site()));
+
+    quote!(
+        impl<#(#params),*> #krate::__private::serde::Serialize for #request_enum_ident #ty_args
+        where
+            #serialize_bounds
+        {
+            fn serialize<#s>(&self, serializer: #s) -> std::result::Result<#s::Ok, #s::Error>
+            where
+                #s: #krate::__private::serde::Serializer,
+            {
+                use #krate::__private::serde::ser::SerializeTuple;
+
+                let mut tuple = serializer.serialize_tuple(2)?;
+                match self {
+                    #(#serialize_arms)*
+                    #request_enum_ident::__Unknown(key) => {
+                        tuple.serialize_element(key)?;
+                        tuple.serialize_element(&[(); 0])?;
+                    }
+                    #phantom_arm
+                }
+                tuple.end()
+            }
+        }
+
+        impl<'de, #(#params),*> #krate::__private::serde::Deserialize<'de> for #request_enum_ident #ty_args
+        where
+            #deserialize_bounds
+        {
+            fn deserialize<#d>(deserializer: #d) -> std::result::Result<Self, #d::Error>
+            where
+                #d: #krate::__private::serde::Deserializer<'de>,
+            {
+                #visitor_struct
+
+                impl<'de, #(#params),*> #krate::__private::serde::de::Visitor<'de> for Visitor #ty_args
+                where
+                    #deserialize_bounds
+                {
+                    type Value = #request_enum_ident #ty_args;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str(#expecting)
+                    }
+
+                    fn visit_seq<#a>(self, mut seq: #a) -> std::result::Result<Self::Value, #a::Error>
+                    where
+                        #a: #krate::__private::serde::de::SeqAccess<'de>,
+                    {
+                        let key: #krate::MethodKey = seq.next_element()?.ok_or_else(|| #krate::__private::serde::de::Error::invalid_length(0, &self))?;
+                        let request = match &key {
+                            #(#deserialize_arms)*
+                            _ => {
+                                seq.next_element::<#krate::__private::serde::de::IgnoredAny>()?;
+                                #request_enum_ident::__Unknown(key)
+                            }
+                        };
+                        Ok(request)
+                    }
+                }
+
+                deserializer.deserialize_tuple(2, #visitor)
+            }
+        }
+    )
 }
 
-/// #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// #[derive(Debug, Clone)]
 /// pub enum MyServiceResponse {
 ///     FnName(Result<Return>),
 ///     FnName2(Result<()>),
+///     #[doc(hidden)]
+///     __Error(nitrogen::Error),
 /// }
-fn make_response_enum(input: &ItemTrait) -> ItemEnum {
-    let response_enum_ident = make_response_enum_ident(input);
+fn make_response_enum(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let response_enum_ident = make_response_enum_ident(input, attrs);
 
     let response_enum_items = input.items.iter().filter_map(|item| {
         if let syn::TraitItem::Fn(item_fn) = item {
@@ -120,9 +606,9 @@ fn make_response_enum(input: &ItemTrait) -> ItemEnum {
             let fn_output = &item_fn.sig.output;
 
             let output = if let syn::ReturnType::Type(_ra, ty) = fn_output {
-                quote!( #response_item_ident(nitrogen::Result<#ty>) )
+                quote!( #response_item_ident(#krate::Result<#ty>) )
             } else {
-                quote!( #response_item_ident(nitrogen::Result<()>) )
+                quote!( #response_item_ident(#krate::Result<()>) )
             };
             Some(output)
         } else {
@@ -130,99 +616,448 @@ fn make_response_enum(input: &ItemTrait) -> ItemEnum {
         }
     });
 
-    let response_enum = quote!(
-        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-        pub enum #response_enum_ident { #(#response_enum_items),* }
+    let generics = ServiceGenerics::new(input);
+    let ty_args = generics.ty_args();
+    let phantom = generics.phantom_variant();
+
+    let derives = &attrs.derives;
+    let output = quote!(
+        #[derive(Debug, Clone, #(#derives),*)]
+        pub enum #response_enum_ident #ty_args {
+            #(#response_enum_items,)*
+            #[doc(hidden)]
+            __Error(#krate::Error),
+            #phantom
+        }
     );
 
-    syn::parse(TokenStream::from(response_enum)).unwrap()
+    output
+}
+
+/// 响应在线上只编码 `Result<Return>`，客户端按请求的方法解码
+///
+/// impl serde::Serialize for MyServiceResponse {
+///     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+///         match self {
+///             MyServiceResponse::FnName(result) => serde::Serialize::serialize(result, serializer),
+///             MyServiceResponse::FnName2(result) => serde::Serialize::serialize(result, serializer),
+///             MyServiceResponse::__Error(err) => serde::Serialize::serialize(&nitrogen::Result::<()>::Err(err.clone()), serializer),
+///         }
+///     }
+/// }
+///
+/// impl nitrogen::RpcResponse for MyServiceResponse { ... }
+///
+/// impl nitrogen::RpcRequest<MyServiceResponse> for MyServiceRequest {
+///     fn response_decoder(&self) -> nitrogen::ResponseDecoder<MyServiceResponse> {
+///         match self {
+///             MyServiceRequest::FnName(..) => |frame| nitrogen::decode_response(frame, MyServiceResponse::FnName),
+///             MyServiceRequest::FnName2 => |frame| nitrogen::decode_response(frame, MyServiceResponse::FnName2),
+///             MyServiceRequest::__Unknown(..) => nitrogen::decode_error_response,
+///         }
+///     }
+/// }
+fn make_response_serde(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+
+    let fns = input
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::TraitItem::Fn(item_fn) => Some(item_fn),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let serialize_arms = fns.iter().map(|item_fn| {
+        let enum_item_ident = syn::Ident::new(&to_camel_case(&format!("{}", item_fn.sig.ident)), item_fn.sig.ident.span());
+        quote!( #response_enum_ident::#enum_item_ident(result) => #krate::__private::serde::Serialize::serialize(result, serializer), )
+    });
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let serialize_bounds = ServiceGenerics::bounds(&params, quote!( #krate::__private::serde::Serialize ));
+    let deserialize_bounds = ServiceGenerics::bounds(&params, quote!( #krate::__private::serde::de::DeserializeOwned ));
+    let s = generics.fresh("S");
+
+    let decoder_arms = fns.iter().map(|item_fn| {
+        let enum_item_ident = syn::Ident::new(&to_camel_case(&format!("{}", item_fn.sig.ident)), item_fn.sig.ident.span());
+        let request_pattern = if make_arg_idents(item_fn, &attrs.krate).is_empty() {
+            quote!( #request_enum_ident::#enum_item_ident )
+        } else {
+            quote!( #request_enum_ident::#enum_item_ident(..) )
+        };
+        quote!( #request_pattern => |frame| #krate::decode_response(frame, #response_enum_ident::#enum_item_ident), )
+    });
+
+    let response_phantom_arm = generics.phantom_arm(&response_enum_ident, quote!(*never));
+    let request_phantom_arm = generics.phantom_arm(&request_enum_ident, quote!(*never));
+
+    quote!(
+        impl<#(#params),*> #krate::__private::serde::Serialize for #response_enum_ident #ty_args
+        where
+            #serialize_bounds
+        {
+            fn serialize<#s>(&self, serializer: #s) -> std::result::Result<#s::Ok, #s::Error>
+            where
+                #s: #krate::__private::serde::Serializer,
+            {
+                match self {
+                    #(#serialize_arms)*
+                    #response_enum_ident::__Error(err) => #krate::__private::serde::Serialize::serialize(&#krate::Result::<()>::Err(err.clone()), serializer),
+                    #response_phantom_arm
+                }
+            }
+        }
+
+        impl<#(#params),*> #krate::RpcResponse for #response_enum_ident #ty_args {
+            fn from_error(err: #krate::Error) -> Self {
+                #response_enum_ident::__Error(err)
+            }
+        }
+
+        impl<#(#params),*> #krate::RpcRequest<#response_enum_ident #ty_args> for #request_enum_ident #ty_args
+        where
+            #deserialize_bounds
+        {
+            fn response_decoder(&self) -> #krate::ResponseDecoder<#response_enum_ident #ty_args> {
+                match self {
+                    #(#decoder_arms)*
+                    #request_enum_ident::__Unknown(..) => #krate::decode_error_response,
+                    #request_phantom_arm
+                }
+            }
+        }
+    )
+}
+
+/// 按方法名与 `nitrogen::Value` 参数构造请求、取出响应中的返回值，供 `MyServiceExt::route_dynamic` 与网关使用
+///
+/// impl nitrogen::DynamicRequest for MyServiceRequest {
+///     fn from_dynamic(method: &str, args: Vec<nitrogen::Value>) -> nitrogen::Result<Self> {
+///         // 以 `[method, [args...]]` 经过与线上相同的 Deserialize，参数按位置解码
+///         match nitrogen::decode_dynamic_request::<Self>("MyService", method, args)? {
+///             MyServiceRequest::__Unknown(key) => Err(nitrogen::Error::new(nitrogen::ErrorKind::UnknownMethod, ...)),
+///             req => Ok(req),
+///         }
+///     }
+/// }
+///
+/// impl nitrogen::DynamicResponse for MyServiceResponse {
+///     fn into_dynamic(self) -> nitrogen::Result<nitrogen::Value> {
+///         match self {
+///             MyServiceResponse::FnName(result) => nitrogen::encode_dynamic_response("MyService", "fn_name", result),
+///             MyServiceResponse::__Error(err) => Err(err),
+///         }
+///     }
+/// }
+fn make_dynamic_impls(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+    let name = attrs.service_name(input);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let serialize_bounds = ServiceGenerics::bounds(&params, quote!( #krate::__private::serde::Serialize ));
+    let deserialize_bounds = ServiceGenerics::bounds(&params, quote!( #krate::__private::serde::de::DeserializeOwned ));
+    let type_name_bound = attrs.type_name_bound(&quote!( #request_enum_ident #ty_args ));
+    let name_expr = if attrs.typed_name {
+        quote!( <#request_enum_ident #ty_args>::service_name() )
+    } else {
+        quote!( #name )
+    };
+
+    let response_arms = input.items.iter().filter_map(|item| match item {
+        syn::TraitItem::Fn(item_fn) => {
+            let enum_item_ident = syn::Ident::new(&to_camel_case(&format!("{}", item_fn.sig.ident)), item_fn.sig.ident.span());
+            let wire_name = parse_method_attrs(item_fn)
+                .unwrap_or_default()
+                .name
+                .unwrap_or_else(|| item_fn.sig.ident.to_string());
+            Some(quote!( #response_enum_ident::#enum_item_ident(result) => #krate::encode_dynamic_response(#name_expr, #wire_name, result), ))
+        }
+        _ => None,
+    });
+    let phantom_arm = generics.phantom_arm(&response_enum_ident, quote!(never));
+
+    quote!(
+        impl<#(#params),*> #krate::DynamicRequest for #request_enum_ident #ty_args
+        where
+            #deserialize_bounds
+            #type_name_bound
+        {
+            fn from_dynamic(method: &str, args: ::std::vec::Vec<#krate::Value>) -> #krate::Result<Self> {
+                match #krate::decode_dynamic_request::<Self>(#name_expr, method, args)? {
+                    #request_enum_ident::__Unknown(key) => Err(#krate::Error::new(
+                        #krate::ErrorKind::UnknownMethod,
+                        format!("{}::{} unknown method", #name_expr, key),
+                    )),
+                    req => Ok(req),
+                }
+            }
+        }
+
+        impl<#(#params),*> #krate::DynamicResponse for #response_enum_ident #ty_args
+        where
+            #serialize_bounds
+            #type_name_bound
+        {
+            fn into_dynamic(self) -> #krate::Result<#krate::Value> {
+                match self {
+                    #(#response_arms)*
+                    #response_enum_ident::__Error(err) => Err(err),
+                    #phantom_arm
+                }
+            }
+        }
+    )
 }
 
 // --- 生成服务扩展 ---
 
-/// #[async_trait::async_trait]
-/// pub trait MyServiceExt<Req, Resp>: MyService
+/// `route` 返回 `impl Future + Send`，本身不分配 boxed future，擦除类型的 `Service` 再为每个请求 box 一次；
+/// 服务名与描述放在这里而不是服务 trait 上，服务 trait 才能用作 `dyn MyService`
+///
+/// 泛型 trait 的扩展带上 trait 的类型参数，如 `KvStoreExt<K, V, Req, Resp>: KvStore<K, V> + Sized`；
+/// 生成的类型参数与 trait 的类型参数重名时改用 `Req0` 等名称
+///
+/// pub trait MyServiceExt<Req, Resp>: MyService + Sized
 /// where
 ///     Req: serde::de::DeserializeOwned + Send + 'static,
-///     Resp: serde::Serialize + Send + 'static,
+///     Resp: serde::Serialize + nitrogen::RpcResponse + Send + 'static,
 /// {
-///     async fn route(&self, req: Req) -> Resp;
+///     const NAME: &'static str = "MyService";
+///     const SCHEMA: nitrogen::ServiceSchema = MyServiceRequest::SCHEMA;
+///
+///     // 指定 `typed_name` 时为 `MyServiceRequest::<..>::service_name()`
+///     fn name() -> &'static str {
+///         Self::NAME
+///     }
+///
+///     fn route(&self, req: Req) -> impl Future<Output = Resp> + Send;
+///
+///     // 由 `nitrogen::DynamicRequest` 构造请求后交给 `route`
+///     fn route_dynamic(&self, method: &str, args: Vec<nitrogen::Value>) -> impl Future<Output = nitrogen::Result<nitrogen::Value>> + Send
+///     where
+///         Req: nitrogen::DynamicRequest,
+///         Resp: nitrogen::DynamicResponse,
+///     { ... }
+///
+///     fn serve<S>(self, stream: S) -> impl Future<Output = ()> + Send
+///     where
+///         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+///     {
+///         self.serve_with_config(stream, nitrogen::CodecConfig::default())
+///     }
+///
+///     fn serve_with_config<S>(self, mut stream: S, config: nitrogen::CodecConfig) -> impl Future<Output = ()> + Send
+///     where
+///         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+///     {
+///         async move {
+///             let handshake = match nitrogen::accept_handshake(&mut stream, &config).await { ... };
+///             self.serve_handshake(stream, handshake).await
+///         }
+///     }
 ///
-///     async fn serve<S>(self, framed_io: nitrogen::FramedTokioIO<S>)
+///     fn serve_handshake<S>(self, stream: S, handshake: nitrogen::IncomingHandshake) -> impl Future<Output = ()> + Send
 ///     where
-///         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+///         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
 ///     {
-///         use futures::{SinkExt, StreamExt};
-///
-///         let (sender, mut receiver) = nitrogen::framed_message_pack::<nitrogen::Message<Req>, nitrogen::Message<Resp>, S>(framed_io).split();
-///         let sender = nitrogen::channel_sender_with_sink(sender);
-///         while let Some(Ok(nitrogen::Message { id, payload })) = receiver.next().await {
-///             let this = self.clone();
-///             let mut sender = sender.clone();
-///             tokio::spawn(async move {
-///                 let payload = this.route(payload).await;
-///                 let _ = sender.send(nitrogen::Message { id, payload }).await;
-///             });
+///         async move {
+///             let name = Self::name();
+///             let service = self.into_service();
+///             let lookup = move |requested: &str| (requested == name).then(|| (service.clone(), nitrogen::ServiceOptions::default()));
+///             if let Err(err) = nitrogen::serve_services(stream, handshake, lookup).await { ... }
 ///         }
 ///     }
+///
+///     // 服务描述中的服务名与 `name()` 相同
+///     fn into_service(self) -> nitrogen::Service {
+///         let name = Self::name();
+///         let this = Arc::new(self);
+///         nitrogen::Service::new(name, move |req: Req| {
+///             let this = this.clone();
+///             async move { <Self as MyServiceExt<Req, Resp>>::route(&this, req).await }
+///         })
+///         .with_schema(nitrogen::ServiceSchema { name: Cow::Borrowed(name), ..Self::SCHEMA })
+///     }
+///
+///     fn from_factory<F>(factory: F) -> nitrogen::Service
+///     where
+///         F: nitrogen::ServiceFactory<Service = Self>,
+///     {
+///         let name = Self::name();
+///         nitrogen::Service::from_factory(name, factory, |this: Arc<Self>, req: Req| async move {
+///             <Self as MyServiceExt<Req, Resp>>::route(&this, req).await
+///         })
+///         .with_schema(nitrogen::ServiceSchema { name: Cow::Borrowed(name), ..Self::SCHEMA })
+///     }
 /// }
-fn make_ext_trait(input: &ItemTrait) -> proc_macro2::TokenStream {
-    let trait_ident = input.ident.clone();
-    let ext_trait_ident = make_ext_trait_ident(input);
+fn make_ext_trait(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let ext_trait_ident = make_ext_trait_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let name = attrs.service_name(input);
+
+    let generics = ServiceGenerics::new(input);
+    let trait_path = generics.trait_path();
+    let params = &generics.params;
+    let predicates = &generics.predicates;
+    let schema_args = ServiceGenerics::turbofish(&generics.args_for(&quote!(Self)));
+    let schema_ty_args = ServiceGenerics::angle(&generics.args_for(&quote!(Self)));
+    let req = generics.fresh("Req");
+    let resp = generics.fresh("Resp");
+    let s = generics.fresh("S");
+    let f = generics.fresh("F");
+    let ext_path = quote!( #ext_trait_ident<#(#params,)* #req, #resp> );
+
+    let type_name_bound = attrs.type_name_bound(&quote!( #request_enum_ident #schema_ty_args ));
+    let name_body = if attrs.typed_name {
+        quote!( #request_enum_ident #schema_args::service_name() )
+    } else {
+        quote!(Self::NAME)
+    };
 
     let output = quote!(
-        #[async_trait::async_trait]
-        pub trait #ext_trait_ident<Req, Resp>: #trait_ident
+        pub trait #ext_trait_ident<#(#params,)* #req, #resp>: #trait_path + Sized
         where
-            Req: serde::de::DeserializeOwned + Send + 'static,
-            Resp: serde::Serialize + Send + 'static,
+            #(#predicates,)*
+            #type_name_bound
+            #req: #krate::__private::serde::de::DeserializeOwned + Send + 'static,
+            #resp: #krate::__private::serde::Serialize + #krate::RpcResponse + Send + 'static,
         {
-            async fn route(&self, req: Req) -> Resp;
+            /// 线上服务名，用于握手与 `nitrogen::Router` 分发
+            const NAME: &'static str = #name;
+            const SCHEMA: #krate::ServiceSchema = #request_enum_ident #schema_args::SCHEMA;
 
-            async fn serve<S>(self, stream: S)
+            /// 握手与分发实际使用的服务名，指定 `typed_name` 时带有类型实例化
+            fn name() -> &'static str {
+                #name_body
+            }
+
+            fn route(&self, req: #req) -> impl ::core::future::Future<Output = #resp> + Send;
+
+            /// 按方法的线上名称与按位置排列的参数处理请求，参数与返回值都是 `nitrogen::Value`
+            fn route_dynamic(&self, method: &str, args: ::std::vec::Vec<#krate::Value>) -> impl ::core::future::Future<Output = #krate::Result<#krate::Value>> + Send
             where
-                S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+                #req: #krate::DynamicRequest,
+                #resp: #krate::DynamicResponse,
             {
-                use futures::{SinkExt, StreamExt};
-
-                let framed_io = tokio_util::codec::LengthDelimitedCodec::builder().max_frame_length(1024 * 1024 * 16).new_framed(stream);
-                let (sender, mut receiver) = nitrogen::framed_message_pack::<nitrogen::Message<Req>, nitrogen::Message<Resp>, S>(framed_io).split();
-                let sender = nitrogen::channel_sender_with_sink(sender);
-                while let Some(Ok(nitrogen::Message { id, payload })) = receiver.next().await {
-                    let this = self.clone();
-                    let mut sender = sender.clone();
-                    tokio::spawn(async move {
-                        let payload = this.route(payload).await;
-                        let _ = sender.send(nitrogen::Message { id, payload }).await;
-                    });
+                let req = <#req as #krate::DynamicRequest>::from_dynamic(method, args);
+                async move { #krate::DynamicResponse::into_dynamic(<Self as #ext_path>::route(self, req?).await) }
+            }
+
+            fn serve<#s>(self, stream: #s) -> impl ::core::future::Future<Output = ()> + Send
+            where
+                #s: #krate::__private::tokio::io::AsyncRead + #krate::__private::tokio::io::AsyncWrite + Send + Unpin + 'static,
+            {
+                self.serve_with_config(stream, #krate::CodecConfig::default())
+            }
+
+            fn serve_with_config<#s>(self, mut stream: #s, config: #krate::CodecConfig) -> impl ::core::future::Future<Output = ()> + Send
+            where
+                #s: #krate::__private::tokio::io::AsyncRead + #krate::__private::tokio::io::AsyncWrite + Send + Unpin + 'static,
+            {
+                async move {
+                    let handshake = match #krate::accept_handshake(&mut stream, &config).await {
+                        Ok(handshake) => handshake,
+                        Err(err) => {
+                            #krate::__private::tracing::error!("{}::serve handshake error: {}", <Self as #ext_path>::name(), err);
+                            return;
+                        }
+                    };
+
+                    self.serve_handshake(stream, handshake).await
+                }
+            }
+
+            /// 回复已由调用方读取的握手并开始处理请求
+            fn serve_handshake<#s>(self, stream: #s, handshake: #krate::IncomingHandshake) -> impl ::core::future::Future<Output = ()> + Send
+            where
+                #s: #krate::__private::tokio::io::AsyncRead + #krate::__private::tokio::io::AsyncWrite + Send + Unpin + 'static,
+            {
+                async move {
+                    let name = <Self as #ext_path>::name();
+                    let service = self.into_service();
+                    let lookup = move |requested: &str| (requested == name).then(|| (service.clone(), #krate::ServiceOptions::default()));
+                    if let Err(err) = #krate::serve_services(stream, handshake, lookup).await {
+                        #krate::__private::tracing::error!("{}::serve error: {}", name, err);
+                    }
                 }
             }
+
+            /// 转换为可注册到 `nitrogen::Router` 的服务，各请求通过 `Arc` 共享实现
+            fn into_service(self) -> #krate::Service {
+                let name = <Self as #ext_path>::name();
+                let this = ::std::sync::Arc::new(self);
+                #krate::Service::new(name, move |req: #req| {
+                    let this = this.clone();
+                    async move { <Self as #ext_path>::route(&this, req).await }
+                })
+                .with_schema(#krate::ServiceSchema {
+                    name: std::borrow::Cow::Borrowed(name),
+                    ..Self::SCHEMA
+                })
+            }
+
+            /// 由 `factory` 为每个连接或流创建实例的服务，会话状态保存在实例上
+            fn from_factory<#f>(factory: #f) -> #krate::Service
+            where
+                #f: #krate::ServiceFactory<Service = Self>,
+            {
+                let name = <Self as #ext_path>::name();
+                #krate::Service::from_factory(name, factory, |this: ::std::sync::Arc<Self>, req: #req| async move {
+                    <Self as #ext_path>::route(&this, req).await
+                })
+                .with_schema(#krate::ServiceSchema {
+                    name: std::borrow::Cow::Borrowed(name),
+                    ..Self::SCHEMA
+                })
+            }
         }
     );
 
     output
 }
 
-/// #[async_trait::async_trait]
 /// impl<T> MyServiceExt<MyServiceRequest, MyServiceResponse> for T
 /// where
 ///     T: MyService,
 /// {
 ///     async fn route(&self, req: MyServiceRequest) -> MyServiceResponse {
 ///         match req {
-///             MyServiceRequest::FnName(arg1, arg2, arg3) => MyServiceResponse::FnName(Ok(self.fn_name(arg1, arg2, arg3).await)),
+///             MyServiceRequest::FnName(arg0, arg1, arg2) => MyServiceResponse::FnName(Ok(self.fn_name(arg0, arg1, arg2).await)),
 ///             MyServiceRequest::FnName2 => MyServiceResponse::FnName2(Ok(self.fn_name2().await)),
+///             // 引用参数借出请求中保存的值
+///             MyServiceRequest::Hello(arg0) => MyServiceResponse::Hello(Ok(self.hello(&arg0).await)),
+///             // `&Context` 参数取当前请求的上下文
+///             MyServiceRequest::Whoami => MyServiceResponse::Whoami(Ok(self.whoami(&nitrogen::Context::current().unwrap_or_default()).await)),
+///             MyServiceRequest::__Unknown(key) => MyServiceResponse::__Error(nitrogen::Error::new(nitrogen::ErrorKind::UnknownMethod, ...)),
 ///         }
 ///     }
 /// }
-fn make_ext_impl(input: &ItemTrait) -> proc_macro2::TokenStream {
-    let trait_ident = input.ident.clone();
-    let ext_trait_ident = make_ext_trait_ident(input);
-    let request_enum_ident = make_request_enum_ident(input);
-    let response_enum_ident = make_response_enum_ident(input);
+///
+/// 泛型 trait 的请求与响应以 `T` 的关联类型实例化，类型参数与关联类型都要能在线上传输：
+///
+/// impl<K, V, T> KvStoreExt<K, V, KvStoreRequest<K, V, <T as KvStore<K, V>>::Cursor>, KvStoreResponse<...>> for T
+/// where
+///     T: KvStore<K, V>,
+///     K: serde::Serialize + serde::de::DeserializeOwned + Send + Sync + 'static,
+///     ...
+fn make_ext_impl(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let ext_trait_ident = make_ext_trait_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
 
     let ext_enum_match = input.items.iter().filter_map(|item| {
-        // MyServiceRequest::FnName(arg1, arg2, arg3) => MyServiceResponse::FnName(Ok(self.fn_name(arg1, arg2, arg3).await)),
+        // MyServiceRequest::FnName(arg0, arg1, arg2) => MyServiceResponse::FnName(Ok(self.fn_name(arg0, arg1, arg2).await)),
         // Or:
         // MyServiceRequest::FnName2 => MyServiceResponse::FnName2(Ok(self.fn_name2().await)),
 
@@ -231,20 +1066,32 @@ fn make_ext_impl(input: &ItemTrait) -> proc_macro2::TokenStream {
             let enum_item_ident = syn::Ident::new(&ident_name, item_fn.sig.ident.span());
             let fn_item_ident = syn::Ident::new(&format!("{}", item_fn.sig.ident), item_fn.sig.ident.span());
 
-            let fn_inputs = item_fn
+            let fn_inputs = make_arg_idents(item_fn, &attrs.krate);
+            let mut arg_idents = fn_inputs.iter();
+            let fn_call_args = item_fn
                 .sig
                 .inputs
                 .iter()
                 .filter_map(|fn_input| match fn_input {
                     syn::FnArg::Receiver(_receiver) => None,
-                    syn::FnArg::Typed(pat_type) => Some(pat_type.pat.clone()),
+                    syn::FnArg::Typed(pat_type) => Some(pat_type),
+                })
+                .map(|pat_type| {
+                    if is_context_arg(pat_type, &attrs.krate) {
+                        return quote!( &#krate::Context::current().unwrap_or_default() );
+                    }
+                    let arg_ident = arg_idents.next();
+                    match &*pat_type.ty {
+                        syn::Type::Reference(_) => quote!( &#arg_ident ),
+                        _ => quote!( #arg_ident ),
+                    }
                 })
                 .collect::<Vec<_>>();
 
             let output = if fn_inputs.is_empty() {
-                quote!( #request_enum_ident::#enum_item_ident => #response_enum_ident::#enum_item_ident(Ok(self.#fn_item_ident().await)) )
+                quote!( #request_enum_ident::#enum_item_ident => #response_enum_ident::#enum_item_ident(Ok(self.#fn_item_ident(#(#fn_call_args),*).await)) )
             } else {
-                quote!( #request_enum_ident::#enum_item_ident(#(#fn_inputs),*) => #response_enum_ident::#enum_item_ident(Ok(self.#fn_item_ident(#(#fn_inputs),*).await)) )
+                quote!( #request_enum_ident::#enum_item_ident(#(#fn_inputs),*) => #response_enum_ident::#enum_item_ident(Ok(self.#fn_item_ident(#(#fn_call_args),*).await)) )
             };
 
             Some(output)
@@ -253,14 +1100,35 @@ fn make_ext_impl(input: &ItemTrait) -> proc_macro2::TokenStream {
         }
     });
 
+    let generics = ServiceGenerics::new(input);
+    let t = generics.fresh("T");
+    let trait_path = generics.trait_path();
+    let params = &generics.params;
+    let predicates = &generics.predicates;
+    let args = generics.args_for(&t.to_token_stream());
+    let ty_args = ServiceGenerics::angle(&args);
+    let wire_bounds = ServiceGenerics::bounds(&args, ServiceGenerics::wire_bound(krate));
+    let type_name_bound = attrs.type_name_bound(&quote!( #request_enum_ident #ty_args ));
+    let ext_path = quote!( #ext_trait_ident<#(#params,)* #request_enum_ident #ty_args, #response_enum_ident #ty_args> );
+    let phantom_arm = generics.phantom_arm(&request_enum_ident, quote!(never));
+
     let output = quote!(
-        #[async_trait::async_trait]
-        impl<T> #ext_trait_ident<#request_enum_ident, #response_enum_ident> for T
+        impl<#(#params,)* #t> #ext_path for #t
         where
-            T: #trait_ident,
+            #t: #trait_path,
+            #(#predicates,)*
+            #wire_bounds
+            #type_name_bound
         {
-            async fn route(&self, req: #request_enum_ident) -> #response_enum_ident {
-                match req { #(#ext_enum_match),* }
+            async fn route(&self, req: #request_enum_ident #ty_args) -> #response_enum_ident #ty_args {
+                match req {
+                    #(#ext_enum_match,)*
+                    #request_enum_ident::__Unknown(key) => #response_enum_ident::__Error(#krate::Error::new(
+                        #krate::ErrorKind::UnknownMethod,
+                        format!("{}::{} unknown method", <Self as #ext_path>::name(), key),
+                    )),
+                    #phantom_arm
+                }
             }
         }
     );
@@ -270,51 +1138,193 @@ fn make_ext_impl(input: &ItemTrait) -> proc_macro2::TokenStream {
 
 // --- 生成客户端实现 ---
 
-/// #[derive(Clone)]
+/// 泛型 trait 的客户端带有与请求枚举相同的类型参数，如 `KvStoreClient<K, V, Cursor>`；
+/// `Clone` 手动实现，不要求类型参数实现 `Clone`
+///
 /// pub struct MyServiceClient {
-///     tx: futures::channel::mpsc::Sender<(MyServiceRequest, futures::channel::oneshot::Sender<MyServiceResponse>)>,
+///     transport: nitrogen::ClientTransport<MyServiceRequest, MyServiceResponse>,
+///     options: nitrogen::CallOptions,
 /// }
-fn make_client_struct(input: &ItemTrait) -> proc_macro2::TokenStream {
-    let client_ident = make_client_ident(input);
-    let request_enum_ident = make_request_enum_ident(input);
-    let response_enum_ident = make_response_enum_ident(input);
+///
+/// impl Clone for MyServiceClient { ... }
+fn make_client_struct(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let client_ident = make_client_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
 
     let output = quote!(
-        #[derive(Clone)]
-        pub struct #client_ident {
-            tx: futures::channel::mpsc::Sender<(#request_enum_ident, futures::channel::oneshot::Sender<#response_enum_ident>)>,
+        pub struct #client_ident #ty_args {
+            transport: #krate::ClientTransport<#request_enum_ident #ty_args, #response_enum_ident #ty_args>,
+            options: #krate::CallOptions,
+        }
+
+        impl<#(#params),*> Clone for #client_ident #ty_args {
+            fn clone(&self) -> Self {
+                Self {
+                    transport: self.transport.clone(),
+                    options: self.options.clone(),
+                }
+            }
         }
     );
 
     output
 }
 
-/// impl MyServiceClient
-/// where
-///     S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
-/// {
-///     pub fn new(session: Session) -> Self {
-///         use futures::channel::{mpsc, oneshot};
-///         use nitrogen::RpcServiceClient;
-///         let (tx, rx) = mpsc::channel::<(MyServiceRequest, oneshot::Sender<MyServiceResponse>)>(128);
-///         Self { tx }.spawn(session, rx)
+/// 泛型 trait 的客户端要求类型参数可以在线上传输，接受实现的方法要求实现的关联类型与客户端的类型参数相同，
+/// 如 `T: KvStore<K, V, Cursor = Cursor>`
+///
+/// impl MyServiceClient {
+///     pub fn new<S>(stream: S) -> Self
+///     where
+///         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+///     {
+///         Self::with_config(stream, nitrogen::CodecConfig::default())
+///     }
+///
+///     pub fn with_config<S>(stream: S, config: nitrogen::CodecConfig) -> Self
+///     where
+///         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+///     {
+///         let name = <Self as nitrogen::RpcServiceClient<MyServiceRequest, MyServiceResponse>>::name();
+///         Self::with_session(&nitrogen::Session::new(stream, &[name], config))
+///     }
+///
+///     pub fn with_session(session: &nitrogen::Session) -> Self {
+///         Self::with_transport(nitrogen::ClientTransport::Session(session.clone()))
+///     }
+///
+///     pub fn with_transport(transport: nitrogen::ClientTransport<MyServiceRequest, MyServiceResponse>) -> Self {
+///         Self { transport, options: nitrogen::CallOptions::default() }
+///     }
+///
+///     pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self { ... }
+///
+///     pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self { ... }
+///
+///     pub fn from_service<T: MyService>(service: T) -> Self {
+///         Self::with_session(&nitrogen::Session::from_services(vec![MyServiceExt::into_service(service)]))
+///     }
+///
+///     pub fn local<T: MyService>(service: T) -> Self {
+///         Self::with_transport(nitrogen::ClientTransport::Local(Self::local_transport(service)))
+///     }
+///
+///     pub fn local_serialized<T: MyService>(service: T) -> Self {
+///         Self::with_transport(nitrogen::ClientTransport::Local(Self::local_transport(service).with_serialization(true)))
+///     }
+///
+///     fn local_transport<T: MyService>(service: T) -> nitrogen::LocalTransport<MyServiceRequest, MyServiceResponse> {
+///         let service = Arc::new(service);
+///         nitrogen::LocalTransport::new(move |req| {
+///             let service = service.clone();
+///             async move { MyServiceExt::route(&*service, req).await }
+///         })
 ///     }
 /// }
-fn make_client_impl_new(input: &ItemTrait) -> proc_macro2::TokenStream {
-    let client_ident = make_client_ident(input);
-    let request_enum_ident = make_request_enum_ident(input);
-    let response_enum_ident = make_response_enum_ident(input);
+fn make_client_impl_new(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let ext_trait_ident = make_ext_trait_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+    let client_ident = make_client_ident(input, attrs);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let trait_params = &generics.params;
+    let predicates = &generics.predicates;
+    let client_bounds = generics.client_bounds(attrs, &request_enum_ident);
+    let trait_path = generics.trait_path_with_assoc();
+    let s = generics.fresh("S");
+    let t = generics.fresh("T");
+    let request_ty = quote!( #request_enum_ident #ty_args );
+    let response_ty = quote!( #response_enum_ident #ty_args );
+    let ext_path = quote!( #ext_trait_ident<#(#trait_params,)* #request_ty, #response_ty> );
 
     let output = quote!(
-        impl #client_ident {
-            pub fn new<S>(stream: S) -> Self
+        impl<#(#params),*> #client_ident #ty_args
+        where
+            #client_bounds
+        {
+            pub fn new<#s>(stream: #s) -> Self
+            where
+                #s: #krate::__private::tokio::io::AsyncRead + #krate::__private::tokio::io::AsyncWrite + Send + Unpin + 'static,
+            {
+                Self::with_config(stream, #krate::CodecConfig::default())
+            }
+
+            pub fn with_config<#s>(stream: #s, config: #krate::CodecConfig) -> Self
             where
-                S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
-             {
-                use futures::channel::{mpsc, oneshot};
-                use nitrogen::RpcServiceClient;
-                let (tx, rx) = mpsc::channel::<(#request_enum_ident, oneshot::Sender<#response_enum_ident>)>(128);
-                Self { tx }.spawn(rx, stream)
+                #s: #krate::__private::tokio::io::AsyncRead + #krate::__private::tokio::io::AsyncWrite + Send + Unpin + 'static,
+            {
+                let name = <Self as #krate::RpcServiceClient<#request_ty, #response_ty>>::name();
+                Self::with_session(&#krate::Session::new(stream, &[name], config))
+            }
+
+            /// 共享已有的会话，会话需要在握手时请求了该服务
+            pub fn with_session(session: &#krate::Session) -> Self {
+                Self::with_transport(#krate::ClientTransport::Session(session.clone()))
+            }
+
+            pub fn with_transport(transport: #krate::ClientTransport<#request_ty, #response_ty>) -> Self {
+                Self {
+                    transport,
+                    options: #krate::CallOptions::default(),
+                }
+            }
+
+            /// 等待响应的时间，默认为 5 秒；服务端从 `Context::deadline` 得知
+            pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+                self.options.timeout = timeout;
+                self
+            }
+
+            /// 随之后的每个请求发送的元数据，服务端从 `Context::metadata` 读取
+            pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+                self.options.metadata.insert(key.into(), value.into());
+                self
+            }
+
+            /// 由进程内的实现处理请求，请求与响应仍经过编解码，用于测试调用方
+            pub fn from_service<#t: #trait_path>(service: #t) -> Self
+            where
+                #(#predicates,)*
+            {
+                let service = <#t as #ext_path>::into_service(service);
+                Self::with_session(&#krate::Session::from_services(vec![service]))
+            }
+
+            /// 在进程内直接调用实现，请求与响应不经过编解码；超时与远程调用相同
+            pub fn local<#t: #trait_path>(service: #t) -> Self
+            where
+                #(#predicates,)*
+            {
+                Self::with_transport(#krate::ClientTransport::Local(Self::local_transport(service)))
+            }
+
+            /// 同 `local`，但请求与响应仍编解码一次，用于发现无法序列化的类型
+            pub fn local_serialized<#t: #trait_path>(service: #t) -> Self
+            where
+                #(#predicates,)*
+            {
+                Self::with_transport(#krate::ClientTransport::Local(Self::local_transport(service).with_serialization(true)))
+            }
+
+            fn local_transport<#t: #trait_path>(service: #t) -> #krate::LocalTransport<#request_ty, #response_ty>
+            where
+                #(#predicates,)*
+            {
+                let service = ::std::sync::Arc::new(service);
+                #krate::LocalTransport::new(move |req| {
+                    let service = service.clone();
+                    async move { <#t as #ext_path>::route(&*service, req).await }
+                })
             }
         }
     );
@@ -325,22 +1335,54 @@ fn make_client_impl_new(input: &ItemTrait) -> proc_macro2::TokenStream {
 /// impl nitrogen::RpcServiceClient<MyServiceRequest, MyServiceResponse> for MyServiceClient {
 ///     const NAME: &'static str = "MyService";
 ///
-///     fn tx(&self) -> futures::channel::mpsc::Sender<(MyServiceRequest, futures::channel::oneshot::Sender<MyServiceResponse>)> {
-///         self.tx.clone()
+///     // 指定 `typed_name` 时
+///     fn name() -> &'static str {
+///         MyServiceRequest::<..>::service_name()
+///     }
+///
+///     fn transport(&self) -> &nitrogen::ClientTransport<MyServiceRequest, MyServiceResponse> {
+///         &self.transport
+///     }
+///
+///     fn options(&self) -> &nitrogen::CallOptions {
+///         &self.options
 ///     }
 /// }
-fn make_client_impl_trait(input: &ItemTrait) -> proc_macro2::TokenStream {
-    let trait_ident = input.ident.clone();
-    let client_ident = make_client_ident(input);
-    let request_enum_ident = make_request_enum_ident(input);
-    let response_enum_ident = make_response_enum_ident(input);
+fn make_client_impl_trait(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let name = attrs.service_name(input);
+    let client_ident = make_client_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let client_bounds = generics.client_bounds(attrs, &request_enum_ident);
+    let turbofish = ServiceGenerics::turbofish(&params);
+    let typed_name = attrs.typed_name.then(|| {
+        quote!(
+            fn name() -> &'static str {
+                #request_enum_ident #turbofish::service_name()
+            }
+        )
+    });
 
     let output = quote!(
-        impl nitrogen::RpcServiceClient<#request_enum_ident, #response_enum_ident> for #client_ident {
-            const NAME: &'static str = stringify!(#trait_ident);
+        impl<#(#params),*> #krate::RpcServiceClient<#request_enum_ident #ty_args, #response_enum_ident #ty_args> for #client_ident #ty_args
+        where
+            #client_bounds
+        {
+            const NAME: &'static str = #name;
+
+            #typed_name
 
-            fn tx(&self) -> futures::channel::mpsc::Sender<(#request_enum_ident, futures::channel::oneshot::Sender<#response_enum_ident>)> {
-                self.tx.clone()
+            fn transport(&self) -> &#krate::ClientTransport<#request_enum_ident #ty_args, #response_enum_ident #ty_args> {
+                &self.transport
+            }
+
+            fn options(&self) -> &#krate::CallOptions {
+                &self.options
             }
         }
     );
@@ -354,7 +1396,8 @@ fn make_client_impl_trait(input: &ItemTrait) -> proc_macro2::TokenStream {
 ///         let resp = self.request(MyServiceRequest::FnName(arg1, arg2, arg3)).await?;
 ///         match resp {
 ///             MyServiceResponse::FnName(res) => res,
-///             _ => Err(nitrogen::Error(format!("{}::{} error: {:?}", "MyServiceRequest", "fn_name", resp))),
+///             MyServiceResponse::__Error(err) => Err(err),
+///             _ => Err(nitrogen::Error::new(nitrogen::ErrorKind::InvalidResponse, format!("{}::{} error: {:?}", "MyServiceRequest", "fn_name", resp))),
 ///         }
 ///     }
 ///
@@ -363,14 +1406,29 @@ fn make_client_impl_trait(input: &ItemTrait) -> proc_macro2::TokenStream {
 ///         let resp = self.request(MyServiceRequest::FnName2).await?;
 ///         match resp {
 ///             MyServiceResponse::FnName2(res) => res,
-///             _ => Err(nitrogen::Error(format!("{}::{} error: {:?}", "MyServiceRequest", "fn_name2", resp))),
+///             MyServiceResponse::__Error(err) => Err(err),
+///             _ => Err(nitrogen::Error::new(nitrogen::ErrorKind::InvalidResponse, format!("{}::{} error: {:?}", "MyServiceRequest", "fn_name2", resp))),
 ///         }
 ///     }
+///
+///     // 引用参数保持借用的签名，发送前转换为拥有所有权的形式
+///     pub async fn hello(&self, name: &str) -> nitrogen::Result<String> {
+///         use nitrogen::RpcServiceClient;
+///         let resp = self.request(MyServiceRequest::Hello(std::borrow::ToOwned::to_owned(name))).await?;
+///         ...
+///     }
 /// }
-fn make_client_impl_fn(input: &ItemTrait) -> proc_macro2::TokenStream {
-    let client_ident = make_client_ident(input);
-    let request_enum_ident = make_request_enum_ident(input);
-    let response_enum_ident = make_response_enum_ident(input);
+fn make_client_impl_fn(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let client_ident = make_client_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let turbofish = ServiceGenerics::turbofish(&params);
+    let client_bounds = generics.client_bounds(attrs, &request_enum_ident);
 
     let client_impl_fn = input.items.iter().filter_map(|item| {
         if let syn::TraitItem::Fn(item_fn) = item {
@@ -379,7 +1437,8 @@ fn make_client_impl_fn(input: &ItemTrait) -> proc_macro2::TokenStream {
             //     let resp = self.request(MyServiceRequest::FnName(arg1, arg2, arg3)).await?;
             //     match resp {
             //         MyServiceResponse::FnName(res) => res,
-            //         _ => Err(nitrogen::Error(format!("{}::{} error: {:?}", "MyServiceRequest", "fn_name", resp))),
+            //         MyServiceResponse::__Error(err) => Err(err),
+            //         _ => Err(nitrogen::Error::new(nitrogen::ErrorKind::InvalidResponse, format!("{}::{} error: {:?}", "MyServiceRequest", "fn_name", resp))),
             //     }
             // }
             let fn_name_ident = item_fn.sig.ident.clone();
@@ -387,17 +1446,11 @@ fn make_client_impl_fn(input: &ItemTrait) -> proc_macro2::TokenStream {
             let request_item_ident = syn::Ident::new(&item_ty_str, fn_name_ident.span());
             let response_item_ident = syn::Ident::new(&item_ty_str, fn_name_ident.span());
 
-            let fn_sig_inputs = item_fn.sig.inputs.iter().cloned().collect::<Vec<_>>();
-
-            let fn_args_idents = item_fn
-                .sig
-                .inputs
-                .iter()
-                .filter_map(|fn_input| match fn_input {
-                    syn::FnArg::Receiver(_receiver) => None,
-                    syn::FnArg::Typed(pat_type) => Some(pat_type.pat.clone()),
-                })
+            let fn_sig_inputs = make_sig_inputs(item_fn)
+                .into_iter()
+                .filter(|fn_input| !matches!(fn_input, syn::FnArg::Typed(pat_type) if is_context_arg(pat_type, &attrs.krate)))
                 .collect::<Vec<_>>();
+            let fn_args_idents = make_owned_args(&fn_sig_inputs, &attrs.krate);
 
             let fn_result_ty = if let syn::ReturnType::Type(_ra, ty) = &item_fn.sig.output {
                 ty.clone()
@@ -405,19 +1458,25 @@ fn make_client_impl_fn(input: &ItemTrait) -> proc_macro2::TokenStream {
                 syn::parse_quote!(())
             };
 
+            // 没有参数时无法从参数推断出类型参数
             let resp_args = if fn_args_idents.is_empty() {
-                quote!( #request_enum_ident::#request_item_ident )
+                quote!( #request_enum_ident #turbofish::#request_item_ident )
             } else {
-                quote!( #request_enum_ident::#request_item_ident(#(#fn_args_idents),*) )
+                quote!( #request_enum_ident #turbofish::#request_item_ident(#(#fn_args_idents),*) )
             };
 
             let output = quote!(
-                pub async fn #fn_name_ident(#(#fn_sig_inputs),*) -> nitrogen::Result<#fn_result_ty> {
-                    use nitrogen::RpcServiceClient;
+                pub async fn #fn_name_ident(#(#fn_sig_inputs),*) -> #krate::Result<#fn_result_ty> {
+                    use #krate::RpcServiceClient;
                     let resp = self.request(#resp_args).await?;
                     match resp {
                         #response_enum_ident::#response_item_ident(res) => res,
-                        _ => Err(nitrogen::Error(format!("{}::{} error: {:?}", stringify!(#request_enum_ident), stringify!(#fn_name_ident), resp))),
+                        #response_enum_ident::__Error(err) => Err(err),
+                        #[allow(unreachable_patterns)]
+                        _ => Err(#krate::Error::new(
+                            #krate::ErrorKind::InvalidResponse,
+                            format!("{}::{} error: {:?}", stringify!(#request_enum_ident), stringify!(#fn_name_ident), resp),
+                        )),
                     }
                 }
             );
@@ -429,7 +1488,10 @@ fn make_client_impl_fn(input: &ItemTrait) -> proc_macro2::TokenStream {
     });
 
     let output = quote!(
-        impl #client_ident {
+        impl<#(#params),*> #client_ident #ty_args
+        where
+            #client_bounds
+        {
             #(#client_impl_fn)*
         }
     );
@@ -437,26 +1499,995 @@ fn make_client_impl_fn(input: &ItemTrait) -> proc_macro2::TokenStream {
     output
 }
 
+// --- 生成测试替身 ---
+
+/// `#[rpc_service(mock)]` 时生成，每个方法对应一个 `nitrogen::MockMethod`
+///
+/// #[derive(Clone, Debug)]
+/// pub struct MockMyService {
+///     fn_name: nitrogen::MockMethod<(Arg1, Arg2, Arg3), Return>,
+///     fn_name2: nitrogen::MockMethod<(), ()>,
+/// }
+///
+/// impl MockMyService {
+///     pub fn new() -> Self { ... }
+///     pub fn expect_fn_name(&self) -> &nitrogen::MockMethod<(Arg1, Arg2, Arg3), Return> { &self.fn_name }
+///     pub fn expect_fn_name2(&self) -> &nitrogen::MockMethod<(), ()> { &self.fn_name2 }
+///
+///     // 桩返回的 Err 作为传输层错误交给客户端
+///     pub fn client(&self) -> MyServiceClient {
+///         let mock = self.clone();
+///         let session = nitrogen::Session::with_handler(&["MyService"], move |_service, id, frame| {
+///             let response = match nitrogen::decode_message::<nitrogen::Message<MyServiceRequest>>(&frame) {
+///                 Ok(nitrogen::Message { payload: MyServiceRequest::FnName(arg0, arg1, arg2), .. }) => {
+///                     mock.fn_name.call((arg0, arg1, arg2)).map(|ret| MyServiceResponse::FnName(Ok(ret)))
+///                 }
+///                 ...
+///             };
+///             async move { ... nitrogen::encode_message(&nitrogen::Message::new(id, response?)) ... }
+///         });
+///         MyServiceClient::with_session(&session)
+///     }
+/// }
+///
+/// // 桩返回的 Err 无法以 trait 方法的返回值表示，直接 panic；`native` 时不加 async_trait
+/// #[async_trait::async_trait]
+/// impl MyService for MockMyService {
+///     async fn fn_name(&self, arg1: Arg1, arg2: Arg2, arg3: Arg3) -> Return {
+///         self.fn_name.call((arg1, arg2, arg3)).unwrap_or_else(|err| panic!("{}", err))
+///     }
+/// }
+///
+/// 泛型 trait 的替身带有与客户端相同的类型参数，关联类型取同名的类型参数，
+/// 如 `impl<K, V, Cursor> KvStore<K, V> for MockKvStore<K, V, Cursor> { type Cursor = Cursor; ... }`
+fn make_mock(input: &ItemTrait, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let mock_ident = syn::Ident::new(&format!("Mock{}", input.ident), input.ident.span());
+    let client_ident = make_client_ident(input, attrs);
+    let request_enum_ident = make_request_enum_ident(input, attrs);
+    let response_enum_ident = make_response_enum_ident(input, attrs);
+
+    let generics = ServiceGenerics::new(input);
+    let params = generics.idents();
+    let ty_args = generics.ty_args();
+    let trait_path = generics.trait_path();
+    let predicates = &generics.predicates;
+    let client_bounds = generics.client_bounds(attrs, &request_enum_ident);
+    let assoc_types = generics.assoc_types.iter().map(|assoc| quote!( type #assoc = #assoc; ));
+    let request_ty = quote!( #request_enum_ident #ty_args );
+    let response_ty = quote!( #response_enum_ident #ty_args );
+    let phantom_arm = generics.phantom_arm(&request_enum_ident, quote!(never));
+    let (marker_field, marker_init) = match generics.phantom() {
+        Some(phantom) => (Some(quote!( _marker: #phantom, )), Some(quote!( _marker: ::core::marker::PhantomData, ))),
+        None => (None, None),
+    };
+
+    let fns = input
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::TraitItem::Fn(item_fn) => Some(item_fn),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut fields = vec![];
+    let mut field_inits = vec![];
+    let mut expects = vec![];
+    let mut client_arms = vec![];
+    let mut trait_fns = vec![];
+
+    for item_fn in fns {
+        let fn_ident = &item_fn.sig.ident;
+        let enum_item_ident = syn::Ident::new(&to_camel_case(&fn_ident.to_string()), fn_ident.span());
+        let expect_ident = quote::format_ident!("expect_{}", fn_ident);
+        let arg_types = make_arg_types(item_fn, &attrs.krate);
+        let arg_idents = make_arg_idents(item_fn, &attrs.krate);
+        let return_ty = match &item_fn.sig.output {
+            syn::ReturnType::Default => syn::parse_quote!(()),
+            syn::ReturnType::Type(_, ty) => (**ty).clone(),
+        };
+        let method_ty = quote!( #krate::MockMethod<(#(#arg_types,)*), #return_ty> );
+        let method_name = format!("{}::{}", mock_ident, fn_ident);
+
+        fields.push(quote!( #fn_ident: #method_ty ));
+        field_inits.push(quote!( #fn_ident: #krate::MockMethod::new(#method_name) ));
+        expects.push(quote!(
+            pub fn #expect_ident(&self) -> &#method_ty {
+                &self.#fn_ident
+            }
+        ));
+
+        let request_pattern = if arg_idents.is_empty() {
+            quote!( #request_enum_ident::#enum_item_ident )
+        } else {
+            quote!( #request_enum_ident::#enum_item_ident(#(#arg_idents),*) )
+        };
+        client_arms.push(quote!(
+            #request_pattern => mock.#fn_ident.call((#(#arg_idents,)*)).map(|ret| #response_enum_ident::#enum_item_ident(Ok(ret))),
+        ));
+
+        let fn_sig_inputs = make_sig_inputs(item_fn)
+            .into_iter()
+            .map(|fn_input| match fn_input {
+                // 替身不使用请求上下文
+                syn::FnArg::Typed(mut pat_type) if is_context_arg(&pat_type, &attrs.krate) => {
+                    pat_type.pat = Box::new(syn::parse_quote!(_));
+                    syn::FnArg::Typed(pat_type)
+                }
+                fn_input => fn_input,
+            })
+            .collect::<Vec<_>>();
+        let fn_args = make_owned_args(&fn_sig_inputs, &attrs.krate);
+        let output = &item_fn.sig.output;
+        trait_fns.push(quote!(
+            async fn #fn_ident(#(#fn_sig_inputs),*) #output {
+                self.#fn_ident.call((#(#fn_args,)*)).unwrap_or_else(|err| panic!("{}", err))
+            }
+        ));
+    }
+
+    let async_trait = (!attrs.native).then(|| quote!( #[#krate::__private::async_trait] ));
+
+    let output = quote!(
+        /// 用于测试的实现，每个方法的返回值通过 `expect_*` 设置
+        #[derive(Clone, Debug)]
+        pub struct #mock_ident #ty_args {
+            #(#fields,)*
+            #marker_field
+        }
+
+        impl<#(#params),*> Default for #mock_ident #ty_args
+        where
+            #client_bounds
+        {
+            fn default() -> Self {
+                Self {
+                    #(#field_inits,)*
+                    #marker_init
+                }
+            }
+        }
+
+        impl<#(#params),*> #mock_ident #ty_args
+        where
+            #client_bounds
+        {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            #(#expects)*
+
+            /// 由该替身处理请求的客户端，桩返回的 `Err` 作为传输层错误返回给调用方
+            pub fn client(&self) -> #client_ident #ty_args {
+                let name = <#client_ident #ty_args as #krate::RpcServiceClient<#request_ty, #response_ty>>::name();
+                let mock = self.clone();
+                let session = #krate::Session::with_handler(&[name], move |_service, id, frame| {
+                    let response = match #krate::decode_message::<#krate::Message<#request_ty>>(&frame) {
+                        Ok(#krate::Message { payload, .. }) => match payload {
+                            #(#client_arms)*
+                            #request_enum_ident::__Unknown(key) => Ok(#response_enum_ident::__Error(#krate::Error::new(
+                                #krate::ErrorKind::UnknownMethod,
+                                format!("{}::{} unknown method", name, key),
+                            ))),
+                            #phantom_arm
+                        },
+                        Err(err) => Err(#krate::Error::new(#krate::ErrorKind::InvalidRequest, format!("{}::serve decode error: {}", name, err))),
+                    };
+                    let frame = response.and_then(|response: #response_ty| {
+                        #krate::encode_message(&#krate::Message::new(id, response))
+                            .map_err(|err| #krate::Error::new(#krate::ErrorKind::InvalidResponse, format!("{}::serve encode error: {}", name, err)))
+                    });
+                    std::future::ready(frame)
+                });
+                #client_ident::with_session(&session)
+            }
+        }
+
+        #async_trait
+        impl<#(#params),*> #trait_path for #mock_ident #ty_args
+        where
+            #(#predicates,)*
+            #client_bounds
+        {
+            #(#assoc_types)*
+            #(#trait_fns)*
+        }
+    );
+
+    output
+}
+
+// --- rpc_service 属性 ---
+
+/// `#[rpc_service(name = "...", client = "...", request = "...", response = "...", derive(...), crate = "...", mock, native, typed_name)]`
+struct ServiceAttrs {
+    /// 线上服务名，用于握手与 `Router` 分发，默认为 trait 名
+    name: Option<String>,
+    client: Option<syn::Ident>,
+    request: Option<syn::Ident>,
+    response: Option<syn::Ident>,
+    /// 额外添加到请求与响应枚举上的 derive
+    derives: Vec<syn::Path>,
+    /// 生成代码中引用 nitrogen 的路径，通过重新导出的 crate 使用时指定
+    krate: syn::Path,
+    /// 生成 `Mock*` 测试替身
+    mock: bool,
+    /// trait 方法生成为返回 `impl Future + Send` 的原生方法，不经过 async_trait
+    native: bool,
+    /// 泛型 trait 的服务名带有类型实例化，如 `KvStore<String, u32>`
+    typed_name: bool,
+}
+
+impl Default for ServiceAttrs {
+    fn default() -> Self {
+        Self {
+            name: None,
+            client: None,
+            request: None,
+            response: None,
+            derives: vec![],
+            krate: syn::parse_quote!(::nitrogen),
+            mock: false,
+            native: false,
+            typed_name: false,
+        }
+    }
+}
+
+impl ServiceAttrs {
+    fn parse(&mut self, meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("name") {
+            let name: syn::LitStr = meta.value()?.parse()?;
+            if name.value().is_empty() {
+                return Err(syn::Error::new_spanned(name, "service name cannot be empty"));
+            }
+            self.name = Some(name.value());
+        } else if meta.path.is_ident("client") {
+            self.client = Some(meta.value()?.parse::<syn::LitStr>()?.parse()?);
+        } else if meta.path.is_ident("request") {
+            self.request = Some(meta.value()?.parse::<syn::LitStr>()?.parse()?);
+        } else if meta.path.is_ident("response") {
+            self.response = Some(meta.value()?.parse::<syn::LitStr>()?.parse()?);
+        } else if meta.path.is_ident("derive") {
+            meta.parse_nested_meta(|meta| {
+                self.derives.push(meta.path);
+                Ok(())
+            })?;
+        } else if meta.path.is_ident("crate") {
+            self.krate = meta.value()?.parse::<syn::LitStr>()?.parse()?;
+        } else if meta.path.is_ident("mock") {
+            self.mock = true;
+        } else if meta.path.is_ident("native") {
+            self.native = true;
+        } else if meta.path.is_ident("typed_name") {
+            self.typed_name = true;
+        } else {
+            return Err(meta.error(
+                "unsupported rpc_service attribute, expected `name`, `client`, `request`, `response`, `derive`, `crate`, `mock`, `native` or `typed_name`",
+            ));
+        }
+        Ok(())
+    }
+
+    fn service_name(&self, input: &ItemTrait) -> String {
+        self.name.clone().unwrap_or_else(|| input.ident.to_string())
+    }
+
+    /// 指定 `typed_name` 时要求请求枚举的实例化实现 `TypeName`：`KvStoreRequest<K, V>: nitrogen::TypeName,`
+    fn type_name_bound(&self, request_ty: &proc_macro2::TokenStream) -> Option<proc_macro2::TokenStream> {
+        let krate = &self.krate;
+        self.typed_name.then(|| quote!( #request_ty: #krate::TypeName, ))
+    }
+}
+
+// --- rpc 属性 ---
+
+/// 方法上的 `#[rpc(id = 3)]`、`#[rpc(name = "wire_name")]`
+#[derive(Default)]
+struct MethodAttrs {
+    id: Option<u32>,
+    name: Option<String>,
+}
+
+fn is_rpc_attr(attr: &syn::Attribute) -> bool {
+    attr.path().is_ident("rpc")
+}
+
+fn parse_method_attrs(item_fn: &syn::TraitItemFn) -> syn::Result<MethodAttrs> {
+    let mut method_attrs = MethodAttrs::default();
+
+    for attr in item_fn.attrs.iter().filter(|attr| is_rpc_attr(attr)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                let id: syn::LitInt = meta.value()?.parse()?;
+                method_attrs.id = Some(id.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                let name: syn::LitStr = meta.value()?.parse()?;
+                method_attrs.name = Some(name.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported rpc attribute, expected `id = N` or `name = \"...\"`"))
+            }
+        })?;
+    }
+
+    Ok(method_attrs)
+}
+
+/// 参数上的 `#[rpc(default)]`、`#[rpc(default = expr)]`
+fn parse_arg_default(pat_type: &syn::PatType) -> syn::Result<Option<syn::Expr>> {
+    let mut default = None;
+
+    for attr in pat_type.attrs.iter().filter(|attr| is_rpc_attr(attr)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                default = Some(if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse()?
+                } else {
+                    syn::parse_quote!(Default::default())
+                });
+                Ok(())
+            } else {
+                Err(meta.error("unsupported rpc attribute, expected `default` or `default = expr`"))
+            }
+        })?;
+    }
+
+    Ok(default)
+}
+
+// --- 检查 ---
+
+/// 生成代码前检查 trait，一次报告所有错误，每个错误指向对应的源码位置
+fn check_trait(input: &ItemTrait, attrs: &ServiceAttrs) -> syn::Result<()> {
+    let mut results = vec![check_trait_shape(input), check_generated_idents(input, attrs)];
+    if attrs.typed_name && ServiceGenerics::new(input).is_empty() {
+        results.push(Err(syn::Error::new_spanned(
+            &input.ident,
+            "`typed_name` requires a trait with type parameters or associated types",
+        )));
+    }
+    for item in &input.items {
+        if let syn::TraitItem::Fn(item_fn) = item {
+            results.push(check_method(item_fn, &attrs.krate));
+        }
+    }
+    results.push(check_rpc_attrs(input, &attrs.krate));
+
+    combine_errors(results.into_iter().filter_map(Result::err).collect())
+}
+
+/// 生成的类型名互不相同，且与 trait 名不同
+fn check_generated_idents(input: &ItemTrait, attrs: &ServiceAttrs) -> syn::Result<()> {
+    let idents = [
+        input.ident.clone(),
+        make_request_enum_ident(input, attrs),
+        make_response_enum_ident(input, attrs),
+        make_ext_trait_ident(input, attrs),
+        make_client_ident(input, attrs),
+    ];
+
+    let mut errors = vec![];
+    for (index, ident) in idents.iter().enumerate() {
+        if idents[..index].contains(ident) {
+            errors.push(syn::Error::new(ident.span(), format!("generated type name `{}` is used twice", ident)));
+        }
+    }
+
+    combine_errors(errors)
+}
+
+/// trait 本身：只有类型参数，只包含方法与关联类型
+fn check_trait_shape(input: &ItemTrait) -> syn::Result<()> {
+    let mut errors = vec![];
+
+    for param in &input.generics.params {
+        match param {
+            syn::GenericParam::Type(type_param) if type_param.default.is_some() => {
+                errors.push(syn::Error::new_spanned(type_param, "rpc_service type parameters cannot have defaults"));
+            }
+            syn::GenericParam::Type(_) => {}
+            param => errors.push(syn::Error::new_spanned(param, "rpc_service traits can only have type parameters")),
+        }
+    }
+    if let Some(unsafety) = &input.unsafety {
+        errors.push(syn::Error::new_spanned(unsafety, "rpc_service traits cannot be `unsafe`"));
+    }
+    if let Some(auto_token) = &input.auto_token {
+        errors.push(syn::Error::new_spanned(auto_token, "rpc_service traits cannot be `auto`"));
+    }
+
+    for item in &input.items {
+        match item {
+            syn::TraitItem::Fn(_) => {}
+            syn::TraitItem::Type(item_type) if !item_type.generics.params.is_empty() || item_type.generics.where_clause.is_some() => {
+                errors.push(syn::Error::new_spanned(item_type, "generic associated types are not supported"));
+            }
+            syn::TraitItem::Type(item_type) if item_type.default.is_some() => {
+                errors.push(syn::Error::new_spanned(
+                    item_type,
+                    "associated types of rpc_service traits cannot have defaults",
+                ));
+            }
+            syn::TraitItem::Type(item_type) if input.generics.type_params().any(|param| param.ident == item_type.ident) => {
+                errors.push(syn::Error::new_spanned(
+                    &item_type.ident,
+                    format!("associated type `{}` has the same name as a type parameter", item_type.ident),
+                ));
+            }
+            syn::TraitItem::Type(_) => {}
+            syn::TraitItem::Const(item_const) if item_const.ident == "NAME" || item_const.ident == "SCHEMA" => {
+                errors.push(syn::Error::new_spanned(
+                    &item_const.ident,
+                    format!("`{}` is generated by rpc_service", item_const.ident),
+                ));
+            }
+            item => errors.push(syn::Error::new_spanned(
+                item,
+                "rpc_service traits may only contain `async fn` methods and associated types",
+            )),
+        }
+    }
+
+    combine_errors(errors)
+}
+
+/// 方法：`async fn`，接收 `&self`，没有泛型参数与 `&mut` 参数
+fn check_method(item_fn: &syn::TraitItemFn, krate: &syn::Path) -> syn::Result<()> {
+    let sig = &item_fn.sig;
+    let mut errors = vec![];
+
+    if sig.asyncness.is_none() {
+        errors.push(syn::Error::new_spanned(
+            sig.fn_token,
+            format!("rpc method `{}` must be an `async fn`", sig.ident),
+        ));
+    }
+    if let Some(constness) = &sig.constness {
+        errors.push(syn::Error::new_spanned(constness, "rpc methods cannot be `const`"));
+    }
+    if let Some(unsafety) = &sig.unsafety {
+        errors.push(syn::Error::new_spanned(unsafety, "rpc methods cannot be `unsafe`"));
+    }
+    if let Some(abi) = &sig.abi {
+        errors.push(syn::Error::new_spanned(abi, "rpc methods cannot declare an ABI"));
+    }
+    if let Some(variadic) = &sig.variadic {
+        errors.push(syn::Error::new_spanned(variadic, "rpc methods cannot be variadic"));
+    }
+
+    // 类型与常量参数无法在请求枚举中表示；生命周期参数不会带到生成的客户端与实现中，引用参数应省略生命周期
+    for param in &sig.generics.params {
+        let message = match param {
+            syn::GenericParam::Lifetime(_) => format!(
+                "rpc method `{}` cannot have lifetime parameters, elide the lifetimes of reference arguments",
+                sig.ident
+            ),
+            _ => format!("rpc method `{}` cannot have type or const parameters", sig.ident),
+        };
+        errors.push(syn::Error::new_spanned(param, message));
+    }
+    if let Some(where_clause) = &sig.generics.where_clause {
+        errors.push(syn::Error::new_spanned(
+            where_clause,
+            format!("rpc method `{}` cannot have a where clause", sig.ident),
+        ));
+    }
+
+    match sig.receiver() {
+        Some(receiver) if receiver.reference.is_some() && receiver.mutability.is_none() && receiver.colon_token.is_none() => {}
+        Some(receiver) => errors.push(syn::Error::new_spanned(receiver, format!("rpc method `{}` must take `&self`", sig.ident))),
+        None => errors.push(syn::Error::new_spanned(
+            &sig.ident,
+            format!("rpc method `{}` must take `&self` as its first argument", sig.ident),
+        )),
+    }
+
+    let mut has_context = false;
+    for fn_input in &sig.inputs {
+        let syn::FnArg::Typed(pat_type) = fn_input else { continue };
+        if is_context_arg(pat_type, krate) {
+            if has_context {
+                errors.push(syn::Error::new_spanned(pat_type, "rpc methods can take at most one `&Context` argument"));
+            }
+            if let Some(attr) = pat_type.attrs.iter().find(|attr| is_rpc_attr(attr)) {
+                errors.push(syn::Error::new_spanned(
+                    attr,
+                    "the `&Context` argument is not sent over the wire and cannot have #[rpc] attributes",
+                ));
+            }
+            has_context = true;
+        }
+        if let syn::Type::Reference(syn::TypeReference { mutability: Some(_), .. }) = &*pat_type.ty {
+            errors.push(syn::Error::new_spanned(
+                &pat_type.ty,
+                "`&mut` arguments are not supported, pass the value or a shared reference",
+            ));
+        }
+        if let syn::Type::ImplTrait(_) = &*pat_type.ty {
+            errors.push(syn::Error::new_spanned(&pat_type.ty, "`impl Trait` arguments are not supported"));
+        }
+    }
+    if let syn::ReturnType::Type(_, ty) = &sig.output {
+        if let syn::Type::ImplTrait(_) = &**ty {
+            errors.push(syn::Error::new_spanned(ty, "`impl Trait` return types are not supported"));
+        }
+    }
+
+    combine_errors(errors)
+}
+
+fn combine_errors(errors: Vec<syn::Error>) -> syn::Result<()> {
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(mut first) => {
+            first.extend(errors);
+            Err(first)
+        }
+        None => Ok(()),
+    }
+}
+
+/// 检查 rpc 属性：格式正确、id 与线上名称不重复、默认值参数只出现在末尾；
+/// 以及方法名转换后的请求枚举变体不重名，如 `get_x` 与 `getX` 都对应 `GetX`
+fn check_rpc_attrs(input: &ItemTrait, krate: &syn::Path) -> syn::Result<()> {
+    let mut ids = std::collections::HashMap::new();
+    let mut names = std::collections::HashMap::new();
+    let mut variants = std::collections::HashMap::new();
+    let mut errors = vec![];
+
+    for item in &input.items {
+        let syn::TraitItem::Fn(item_fn) = item else { continue };
+        let ident = &item_fn.sig.ident;
+
+        let variant = to_camel_case(&ident.to_string());
+        if let Some(other) = variants.insert(variant.clone(), ident.clone()) {
+            errors.push(syn::Error::new_spanned(
+                ident,
+                format!("`{}` and `{}` both map to the request variant `{}`, rename one of them", other, ident, variant),
+            ));
+        }
+
+        match parse_method_attrs(item_fn) {
+            Ok(method_attrs) => {
+                if let Some(id) = method_attrs.id {
+                    if let Some(other) = ids.insert(id, ident.clone()) {
+                        errors.push(syn::Error::new_spanned(ident, format!("rpc id {} is already used by `{}`", id, other)));
+                    }
+                }
+                let wire_name = method_attrs.name.unwrap_or_else(|| ident.to_string());
+                if let Some(other) = names.insert(wire_name.clone(), ident.clone()) {
+                    errors.push(syn::Error::new_spanned(
+                        ident,
+                        format!("rpc name \"{}\" is already used by `{}`", wire_name, other),
+                    ));
+                }
+            }
+            Err(err) => errors.push(err),
+        }
+
+        let mut has_default = false;
+        for pat_type in wire_args(item_fn, krate) {
+            match parse_arg_default(pat_type) {
+                Ok(Some(_)) => has_default = true,
+                Ok(None) if has_default => errors.push(syn::Error::new_spanned(
+                    pat_type,
+                    "arguments after an argument with #[rpc(default)] must also have a default",
+                )),
+                Ok(None) => {}
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    combine_errors(errors)
+}
+
+/// 去掉 trait 中的 rpc 属性，它们只供本宏使用
+///
+/// 没有默认实现的方法声明中不允许出现模式参数，这些参数换成合成的绑定名，实现中仍可以使用模式。
+fn strip_rpc_attrs(input: &mut ItemTrait) {
+    for item in input.items.iter_mut() {
+        let syn::TraitItem::Fn(item_fn) = item else { continue };
+
+        item_fn.attrs.retain(|attr| !is_rpc_attr(attr));
+        for fn_input in item_fn.sig.inputs.iter_mut() {
+            if let syn::FnArg::Typed(pat_type) = fn_input {
+                pat_type.attrs.retain(|attr| !is_rpc_attr(attr));
+            }
+        }
+        if item_fn.default.is_none() {
+            item_fn.sig.inputs = with_binding_names(item_fn.sig.inputs.iter().cloned()).into_iter().collect();
+        }
+    }
+}
+
+/// `native` 时把 trait 中的 `async fn` 改写为返回 `impl Future + Send` 的方法
+///
+/// async fn fn_name(&self, arg1: Arg1) -> Return;
+/// // 改写为
+/// fn fn_name(&self, arg1: Arg1) -> impl ::core::future::Future<Output = Return> + Send;
+///
+/// 实现方仍然写 `async fn`，返回的 future 不是 `Send` 时在实现处报错，保证服务端可以在任务中处理请求。
+fn make_native_fns(input: &mut ItemTrait) {
+    for item in input.items.iter_mut() {
+        let syn::TraitItem::Fn(item_fn) = item else { continue };
+        let Some(async_token) = item_fn.sig.asyncness.take() else { continue };
+
+        let return_ty = match &item_fn.sig.output {
+            syn::ReturnType::Default => quote!(()),
+            syn::ReturnType::Type(_, ty) => quote!( #ty ),
+        };
+        item_fn.sig.output = syn::parse_quote_spanned!(async_token.span=>
+            -> impl ::core::future::Future<Output = #return_ty> + Send
+        );
+        if let Some(body) = &item_fn.default {
+            item_fn.default = Some(syn::parse_quote_spanned!(body.brace_token.span=> { async move #body }));
+        }
+    }
+}
+
+/// 生成的方法签名：去掉 rpc 属性，模式参数（如 `(a, b): (u32, u32)`）换成合成的绑定名
+fn make_sig_inputs(item_fn: &syn::TraitItemFn) -> Vec<syn::FnArg> {
+    let fn_inputs = item_fn.sig.inputs.iter().map(|fn_input| match fn_input {
+        syn::FnArg::Typed(pat_type) => {
+            let mut pat_type = pat_type.clone();
+            pat_type.attrs.retain(|attr| !is_rpc_attr(attr));
+            syn::FnArg::Typed(pat_type)
+        }
+        receiver => receiver.clone(),
+    });
+    with_binding_names(fn_inputs)
+}
+
+/// [`make_sig_inputs`] 中各参数拥有所有权的形式，引用参数通过 `ToOwned` 转换，请求上下文不在其中
+fn make_owned_args(fn_sig_inputs: &[syn::FnArg], krate: &syn::Path) -> Vec<proc_macro2::TokenStream> {
+    fn_sig_inputs
+        .iter()
+        .filter_map(|fn_input| match fn_input {
+            syn::FnArg::Receiver(_receiver) => None,
+            syn::FnArg::Typed(pat_type) if is_context_arg(pat_type, krate) => None,
+            syn::FnArg::Typed(pat_type) => {
+                let pat = &pat_type.pat;
+                Some(match &*pat_type.ty {
+                    syn::Type::Reference(_) => quote!( std::borrow::ToOwned::to_owned(#pat) ),
+                    _ => quote!( #pat ),
+                })
+            }
+        })
+        .collect()
+}
+
+/// 把不是简单标识符的参数模式换成 `__argN`，`N` 为参数的序号（不含 `self`）
+fn with_binding_names(fn_inputs: impl Iterator<Item = syn::FnArg>) -> Vec<syn::FnArg> {
+    let mut index = 0usize;
+    fn_inputs
+        .map(|fn_input| match fn_input {
+            syn::FnArg::Typed(mut pat_type) => {
+                let is_ident = matches!(&*pat_type.pat, syn::Pat::Ident(pat_ident) if pat_ident.by_ref.is_none() && pat_ident.mutability.is_none() && pat_ident.subpat.is_none());
+                if !is_ident {
+                    let ident = quote::format_ident!("__arg{}", index);
+                    pat_type.pat = Box::new(syn::parse_quote!(#ident));
+                }
+                index += 1;
+                syn::FnArg::Typed(pat_type)
+            }
+            receiver => receiver,
+        })
+        .collect()
+}
+
+/// 客户端发送时使用的方法标识：有 id 时用 id，否则用线上名称
+fn make_method_key(item_fn: &syn::TraitItemFn, attrs: &ServiceAttrs) -> proc_macro2::TokenStream {
+    let krate = &attrs.krate;
+    let method_attrs = parse_method_attrs(item_fn).unwrap_or_default();
+    match method_attrs.id {
+        Some(id) => quote!( #krate::MethodKey::Id(#id) ),
+        None => {
+            let wire_name = method_attrs.name.unwrap_or_else(|| item_fn.sig.ident.to_string());
+            quote!( #krate::MethodKey::name(#wire_name) )
+        }
+    }
+}
+
+/// 服务端识别方法标识：id 与线上名称都能匹配
+fn make_method_matcher(item_fn: &syn::TraitItemFn) -> proc_macro2::TokenStream {
+    let method_attrs = parse_method_attrs(item_fn).unwrap_or_default();
+    let wire_name = method_attrs.name.unwrap_or_else(|| item_fn.sig.ident.to_string());
+    let id = match method_attrs.id {
+        Some(id) => quote!( Some(#id) ),
+        None => quote!(None),
+    };
+    quote!( key.matches(#id, #wire_name) )
+}
+
+// --- 泛型 ---
+
+/// trait 的类型参数与关联类型
+///
+/// 生成的请求、响应枚举与客户端依次以 trait 的类型参数与关联类型为类型参数：
+///
+/// #[rpc_service]
+/// pub trait KvStore<K, V> {
+///     type Cursor;
+///     async fn scan(&self, from: Option<Self::Cursor>) -> (Vec<(K, V)>, Option<Self::Cursor>);
+/// }
+/// // 生成 KvStoreRequest<K, V, Cursor>、KvStoreResponse<K, V, Cursor>、KvStoreClient<K, V, Cursor>
+struct ServiceGenerics {
+    trait_ident: syn::Ident,
+    /// trait 的类型参数
+    params: Vec<syn::Ident>,
+    /// 关联类型
+    assoc_types: Vec<syn::Ident>,
+    /// trait 的类型参数上的约束与 where 子句，提到 trait 的 impl 都要带上
+    predicates: Vec<syn::WherePredicate>,
+}
+
+impl ServiceGenerics {
+    fn new(input: &ItemTrait) -> Self {
+        let params = input.generics.type_params().map(|param| param.ident.clone()).collect();
+        let assoc_types = input
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                syn::TraitItem::Type(item_type) => Some(item_type.ident.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let mut predicates = input
+            .generics
+            .type_params()
+            .filter(|param| !param.bounds.is_empty())
+            .map(|param| {
+                let ident = &param.ident;
+                let bounds = &param.bounds;
+                syn::parse_quote!( #ident: #bounds )
+            })
+            .collect::<Vec<syn::WherePredicate>>();
+        if let Some(where_clause) = &input.generics.where_clause {
+            predicates.extend(where_clause.predicates.iter().cloned());
+        }
+
+        Self {
+            trait_ident: input.ident.clone(),
+            params,
+            assoc_types,
+            predicates,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.params.is_empty() && self.assoc_types.is_empty()
+    }
+
+    /// 生成类型的类型参数：`K, V, Cursor`
+    fn idents(&self) -> Vec<&syn::Ident> {
+        self.params.iter().chain(&self.assoc_types).collect()
+    }
+
+    /// 生成类型的类型参数列表 `<K, V, Cursor>`，不是泛型时为空
+    fn ty_args(&self) -> proc_macro2::TokenStream {
+        Self::angle(&self.idents())
+    }
+
+    /// 以 `ty` 的关联类型实例化生成类型：`K, V, <T as KvStore<K, V>>::Cursor`
+    fn args_for(&self, ty: &proc_macro2::TokenStream) -> Vec<proc_macro2::TokenStream> {
+        let trait_path = self.trait_path();
+        let params = self.params.iter().map(|param| quote!( #param ));
+        let assoc_types = self.assoc_types.iter().map(|assoc| quote!( <#ty as #trait_path>::#assoc ));
+        params.chain(assoc_types).collect()
+    }
+
+    fn angle<T: ToTokens>(args: &[T]) -> proc_macro2::TokenStream {
+        if args.is_empty() {
+            quote!()
+        } else {
+            quote!( <#(#args),*> )
+        }
+    }
+
+    fn turbofish<T: ToTokens>(args: &[T]) -> proc_macro2::TokenStream {
+        if args.is_empty() {
+            quote!()
+        } else {
+            quote!( ::<#(#args),*> )
+        }
+    }
+
+    /// `KvStore<K, V>`
+    fn trait_path(&self) -> proc_macro2::TokenStream {
+        let trait_ident = &self.trait_ident;
+        let params = Self::angle(&self.params);
+        quote!( #trait_ident #params )
+    }
+
+    /// 关联类型固定为同名类型参数：`KvStore<K, V, Cursor = Cursor>`
+    fn trait_path_with_assoc(&self) -> proc_macro2::TokenStream {
+        let trait_ident = &self.trait_ident;
+        let params = self.params.iter().map(|param| quote!( #param ));
+        let assoc_types = self.assoc_types.iter().map(|assoc| quote!( #assoc = #assoc ));
+        let args = Self::angle(&params.chain(assoc_types).collect::<Vec<_>>());
+        quote!( #trait_ident #args )
+    }
+
+    /// 每个参数都满足 `bound`：`K: bound, V: bound,`
+    fn bounds<T: ToTokens>(args: &[T], bound: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        quote!( #(#args: #bound,)* )
+    }
+
+    /// 在线上传输、在任务间共享所需的约束
+    fn wire_bound(krate: &syn::Path) -> proc_macro2::TokenStream {
+        quote!( #krate::__private::serde::Serialize + #krate::__private::serde::de::DeserializeOwned + Send + Sync + 'static )
+    }
+
+    /// 客户端还要以 `Debug` 输出无法识别的响应，以 `Clone` 把引用参数转换为拥有所有权的形式；
+    /// 指定 `typed_name` 时服务名来自请求枚举实现的 `TypeName`
+    fn client_bounds(&self, attrs: &ServiceAttrs, request_enum_ident: &syn::Ident) -> proc_macro2::TokenStream {
+        let wire_bound = Self::wire_bound(&attrs.krate);
+        let bounds = Self::bounds(&self.idents(), quote!( #wire_bound + std::fmt::Debug + Clone ));
+        let ty_args = self.ty_args();
+        let type_name_bound = attrs.type_name_bound(&quote!( #request_enum_ident #ty_args ));
+        quote!( #bounds #type_name_bound )
+    }
+
+    /// 使用所有类型参数的标记类型，不是泛型时为 `None`
+    fn phantom(&self) -> Option<proc_macro2::TokenStream> {
+        let idents = self.idents();
+        (!self.is_empty()).then(|| quote!( ::core::marker::PhantomData<fn() -> (#(#idents,)*)> ))
+    }
+
+    /// 请求、响应枚举中不会构造的变体，使没有出现在参数与返回值中的类型参数也被使用
+    fn phantom_variant(&self) -> Option<proc_macro2::TokenStream> {
+        self.phantom().map(|phantom| {
+            quote!(
+                #[doc(hidden)]
+                __Phantom(::core::convert::Infallible, #phantom),
+            )
+        })
+    }
+
+    /// 匹配 `__Phantom` 变体的分支，`never` 绑定其中的 `Infallible`
+    fn phantom_arm(&self, enum_ident: &syn::Ident, never: proc_macro2::TokenStream) -> Option<proc_macro2::TokenStream> {
+        (!self.is_empty()).then(|| quote!( #enum_ident::__Phantom(never, _) => match #never {}, ))
+    }
+
+    /// 生成代码自己的类型参数名，与 trait 的类型参数或关联类型重名时加上数字后缀，如 `T0`
+    fn fresh(&self, name: &str) -> syn::Ident {
+        let is_used = |candidate: &str| self.idents().iter().any(|ident| *ident == candidate);
+        let candidate = (0..)
+            .map(|index| if index == 0 { name.to_string() } else { format!("{}{}", name, index - 1) })
+            .find(|candidate| !is_used(candidate))
+            .unwrap();
+        syn::Ident::new(&candidate, proc_macro2::Span::call_site())
+    }
+}
+
+/// 线上类型中的 `Self::Cursor`、`<Self as KvStore<K, V>>::Cursor` 换成同名的类型参数 `Cursor`
+fn make_wire_trait(input: &ItemTrait) -> ItemTrait {
+    struct ReplaceAssocTypes(Vec<syn::Ident>);
+
+    impl syn::visit_mut::VisitMut for ReplaceAssocTypes {
+        fn visit_type_path_mut(&mut self, ty: &mut syn::TypePath) {
+            let segments = &ty.path.segments;
+            let assoc = match &ty.qself {
+                None if segments.len() == 2 && segments[0].ident == "Self" && segments[0].arguments.is_none() => Some(&segments[1]),
+                Some(qself)
+                    if matches!(&*qself.ty, syn::Type::Path(path) if path.qself.is_none() && path.path.is_ident("Self"))
+                        && segments.len() == qself.position + 1 =>
+                {
+                    segments.last()
+                }
+                _ => None,
+            };
+            match assoc {
+                Some(assoc) if assoc.arguments.is_none() && self.0.contains(&assoc.ident) => {
+                    *ty = syn::TypePath {
+                        qself: None,
+                        path: assoc.ident.clone().into(),
+                    };
+                }
+                _ => syn::visit_mut::visit_type_path_mut(self, ty),
+            }
+        }
+    }
+
+    let mut wire = input.clone();
+    syn::visit_mut::VisitMut::visit_item_trait_mut(&mut ReplaceAssocTypes(ServiceGenerics::new(input).assoc_types), &mut wire);
+    wire
+}
+
 // --- make_*_ident ---
 
-fn make_request_enum_ident(input: &ItemTrait) -> syn::Ident {
-    syn::Ident::new(&format!("{}Request", input.ident), input.ident.span())
+fn make_request_enum_ident(input: &ItemTrait, attrs: &ServiceAttrs) -> syn::Ident {
+    attrs
+        .request
+        .clone()
+        .unwrap_or_else(|| syn::Ident::new(&format!("{}Request", input.ident), input.ident.span()))
 }
 
-fn make_response_enum_ident(input: &ItemTrait) -> syn::Ident {
-    syn::Ident::new(&format!("{}Response", input.ident), input.ident.span())
+fn make_response_enum_ident(input: &ItemTrait, attrs: &ServiceAttrs) -> syn::Ident {
+    attrs
+        .response
+        .clone()
+        .unwrap_or_else(|| syn::Ident::new(&format!("{}Response", input.ident), input.ident.span()))
 }
 
-fn make_ext_trait_ident(input: &ItemTrait) -> syn::Ident {
+fn make_ext_trait_ident(input: &ItemTrait, _attrs: &ServiceAttrs) -> syn::Ident {
     syn::Ident::new(&format!("{}Ext", input.ident), input.ident.span())
 }
 
-fn make_client_ident(input: &ItemTrait) -> syn::Ident {
-    syn::Ident::new(&format!("{}Client", input.ident), input.ident.span())
+fn make_client_ident(input: &ItemTrait, attrs: &ServiceAttrs) -> syn::Ident {
+    attrs
+        .client
+        .clone()
+        .unwrap_or_else(|| syn::Ident::new(&format!("{}Client", input.ident), input.ident.span()))
+}
+
+/// 请求枚举变体中各参数的绑定名：arg0, arg1, ...
+fn make_arg_idents(item_fn: &syn::TraitItemFn, krate: &syn::Path) -> Vec<syn::Ident> {
+    wire_args(item_fn, krate)
+        .enumerate()
+        .map(|(index, _)| quote::format_ident!("arg{}", index))
+        .collect()
+}
+
+fn make_arg_types(item_fn: &syn::TraitItemFn, krate: &syn::Path) -> Vec<syn::Type> {
+    wire_args(item_fn, krate).map(|pat_type| owned_type(&pat_type.ty)).collect()
+}
+
+/// 在线上传输的参数，即除 `&self` 与请求上下文以外的参数
+fn wire_args<'a>(item_fn: &'a syn::TraitItemFn, krate: &'a syn::Path) -> impl Iterator<Item = &'a syn::PatType> {
+    item_fn.sig.inputs.iter().filter_map(|fn_input| match fn_input {
+        syn::FnArg::Typed(pat_type) if !is_context_arg(pat_type, krate) => Some(pat_type),
+        _ => None,
+    })
+}
+
+/// `&nitrogen::Context` 或 `&<crate>::Context`（`crate` 选项给出的路径）参数由服务端填入当前请求的上下文
+///
+/// 宏看不到 `use` 语句，只按完整路径识别；`&Context` 与其他路径下名为 `Context` 的类型（如 `&app::Context`）是普通参数。
+fn is_context_arg(pat_type: &syn::PatType, krate: &syn::Path) -> bool {
+    let syn::Type::Reference(reference) = &*pat_type.ty else {
+        return false;
+    };
+    let syn::Type::Path(path) = &*reference.elem else {
+        return false;
+    };
+    if path.qself.is_some() || reference.mutability.is_some() || path.path.segments.iter().any(|segment| !segment.arguments.is_none()) {
+        return false;
+    }
+
+    let idents = path.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+    let Some((last, prefix)) = idents.split_last() else {
+        return false;
+    };
+    let krate = krate.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+
+    last == "Context" && (prefix == ["nitrogen"] || prefix == krate.as_slice())
 }
 
 // --- 工具函数 ---
 
+/// 请求枚举中保存的参数类型：`&str` 保存为 `String`，`&[T]` 保存为 `Vec<T>`，其他 `&T` 保存为 `T`
+fn owned_type(ty: &syn::Type) -> syn::Type {
+    let syn::Type::Reference(reference) = ty else {
+        return ty.clone();
+    };
+
+    match &*reference.elem {
+        syn::Type::Path(path) if path.qself.is_none() && path.path.is_ident("str") => syn::parse_quote!(String),
+        syn::Type::Slice(slice) => {
+            let elem = &slice.elem;
+            syn::parse_quote!(Vec<#elem>)
+        }
+        elem => elem.clone(),
+    }
+}
+
 // 下划线变量名转驼峰变量名
 fn to_camel_case(name: &str) -> String {
     let mut result = String::new();
@@ -475,3 +2506,42 @@ fn to_camel_case(name: &str) -> String {
 
     result
 }
+
+/// 以紧凑的形式输出类型，例如 `Vec<u8>`、`(u32, String)`
+fn type_to_string<T: quote::ToTokens>(ty: &T) -> String {
+    let tokens = ty.to_token_stream().to_string();
+    let mut output = String::with_capacity(tokens.len());
+    let mut chars = tokens.chars().peekable();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '\'';
+
+    while let Some(c) = chars.next() {
+        if c == ' ' {
+            let prev = output.chars().last();
+            let next = chars.peek().copied();
+            if prev == Some(',') || matches!((prev, next), (Some(prev), Some(next)) if is_word(prev) && is_word(next)) {
+                output.push(' ');
+            }
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+/// 合并 `///` 文档注释
+fn parse_docs(attrs: &[syn::Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }),
+                ..
+            }) => Some(doc.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
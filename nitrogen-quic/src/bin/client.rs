@@ -2,8 +2,7 @@ use std::time::{Duration, Instant};
 
 use futures::{SinkExt, StreamExt};
 use nitrogen_quic::QuicConnect;
-use nitrogen_utils::{channel_sender_with_sink, framed_message_pack, BiConnect, BiConnnectionOpener};
-use tokio_util::codec::LengthDelimitedCodec;
+use nitrogen_utils::{channel_sender_with_sink, framed_message_pack, framed_tokio_io, BiConnect, BiConnnectionOpener, FrameCodec};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -13,7 +12,7 @@ async fn main() -> anyhow::Result<()> {
 
     let bi_stream = connect.open().await?;
 
-    let framed_io = LengthDelimitedCodec::builder().max_frame_length(1024 * 1024 * 16).new_framed(bi_stream);
+    let framed_io = framed_tokio_io(bi_stream, FrameCodec::default());
 
     let (sender, mut receiver) = framed_message_pack::<String, String, _>(framed_io).split();
     let mut sender = channel_sender_with_sink(sender);
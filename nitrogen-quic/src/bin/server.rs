@@ -1,18 +1,17 @@
 use futures::{SinkExt, StreamExt};
 use nitrogen_quic::QuicListener;
-use nitrogen_utils::{channel_sender_with_sink, framed_message_pack, BiConnnectionAcceptor, BiListener, FramedTokioIO};
+use nitrogen_utils::{channel_sender_with_sink, framed_message_pack, framed_tokio_io, BiConnnectionAcceptor, BiListener, FrameCodec, FramedTokioIO};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::codec::LengthDelimitedCodec;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let mut server = QuicListener::bind("0.0.0.0:31234".parse()?).await?;
 
-    while let Ok(mut connection) = server.accept().await {
+    while let Some(mut connection) = server.accept().await? {
         tokio::spawn(async move {
             while let Ok(bi_stream) = connection.accept().await {
                 tokio::spawn(async move {
-                    let framed_io = LengthDelimitedCodec::builder().max_frame_length(1024 * 1024 * 16).new_framed(bi_stream);
+                    let framed_io = framed_tokio_io(bi_stream, FrameCodec::default());
 
                     handler(framed_io).await
                 });
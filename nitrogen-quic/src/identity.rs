@@ -0,0 +1,124 @@
+use std::net::IpAddr;
+
+use nitrogen_utils::PeerIdentity;
+use s2n_quic::provider::event::{events, ConnectionInfo, ConnectionMeta, Subscriber};
+use sha2::{Digest, Sha256};
+use x509_parser::{extensions::GeneralName, prelude::parse_x509_certificate};
+
+/// 在 TLS 握手完成时记录对端证书的身份，供 [`crate::QuicConnection`] 的 `peer_info` 查询
+#[derive(Debug, Default)]
+pub struct PeerIdentitySubscriber;
+
+/// 每个连接上记录的对端身份，对端没有出示证书时为空
+#[derive(Debug, Default)]
+pub struct PeerIdentityContext(pub Option<PeerIdentity>);
+
+impl Subscriber for PeerIdentitySubscriber {
+    type ConnectionContext = PeerIdentityContext;
+
+    fn create_connection_context(&mut self, _meta: &ConnectionMeta, _info: &ConnectionInfo) -> Self::ConnectionContext {
+        PeerIdentityContext::default()
+    }
+
+    fn on_tls_exporter_ready(&mut self, context: &mut Self::ConnectionContext, _meta: &ConnectionMeta, event: &events::TlsExporterReady) {
+        // 证书链已经由 MtlsProvider 验证，第一张为对端自己的证书
+        match event.session.peer_cert_chain_der() {
+            Ok(chain) => context.0 = chain.first().and_then(|der| parse_peer_identity(der)),
+            Err(err) => tracing::debug!("PeerIdentitySubscriber peer certificate error: {:?}", err),
+        }
+    }
+}
+
+/// 解析 DER 编码的证书的主题与主题备用名称，并计算证书的指纹；证书无法解析时返回 `None`
+///
+/// 备用名称按 OpenSSL 的格式加上 `DNS:`、`URI:`、`email:`、`IP:` 前缀，指纹为小写十六进制的 SHA-256。
+/// 只解析，不验证证书链。
+pub fn parse_peer_identity(der: &[u8]) -> Option<PeerIdentity> {
+    let (_, cert) = parse_x509_certificate(der).ok()?;
+
+    let sans = match cert.subject_alternative_name() {
+        Ok(Some(extension)) => extension
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(name) => Some(format!("DNS:{}", name)),
+                GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+                GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+                GeneralName::IPAddress(ip) => parse_ip(ip).map(|ip| format!("IP:{}", ip)),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    let subject = cert.subject();
+    let common_name = subject.iter_common_name().find_map(|attr| attr.as_str().ok()).map(str::to_string);
+    let organizational_units = subject
+        .iter_organizational_unit()
+        .filter_map(|attr| attr.as_str().ok())
+        .map(str::to_string)
+        .collect();
+    let fingerprint = Sha256::digest(der).iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    Some(PeerIdentity {
+        subject: subject.to_string(),
+        sans,
+        common_name,
+        organizational_units,
+        fingerprint,
+    })
+}
+
+fn parse_ip(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CA_CERT_PEM, MY_CERT_PEM};
+
+    fn load_der(path: &str) -> Vec<u8> {
+        let pem = std::fs::read(path).unwrap();
+        rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0)
+    }
+
+    #[test]
+    fn parses_bundled_certificates() {
+        let client = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/client.crt");
+        let identity = parse_peer_identity(&load_der(client)).unwrap();
+        assert_eq!(identity.subject, "CN=localhost");
+        assert_eq!(identity.common_name.as_deref(), Some("localhost"));
+        assert_eq!(identity.sans, ["DNS:localhost"]);
+        assert!(identity.organizational_units.is_empty());
+        assert_eq!(identity.fingerprint, "9d588373e0cd25437dabd807438ff3a52cdf68055c32587edf2b30dbe3dff8fb");
+
+        let ca = parse_peer_identity(&load_der(CA_CERT_PEM)).unwrap();
+        assert_eq!(ca.common_name.as_deref(), Some("Root CA"));
+        assert!(ca.sans.is_empty());
+        assert_eq!(ca.fingerprint, "712648f2e8c3aff5b62c13619f2326dd553a11988f755231a1e0f80d1bf40cdc");
+
+        // 客户端与服务端证书的主题相同，按指纹区分
+        let server = parse_peer_identity(&load_der(MY_CERT_PEM)).unwrap();
+        assert_eq!(server.common_name, identity.common_name);
+        assert_ne!(server.fingerprint, identity.fingerprint);
+    }
+
+    #[test]
+    fn rejects_invalid_der() {
+        assert!(parse_peer_identity(b"not a certificate").is_none());
+        assert!(parse_peer_identity(&[]).is_none());
+    }
+
+    #[test]
+    fn parses_ip_addresses() {
+        assert_eq!(parse_ip(&[127, 0, 0, 1]), Some(IpAddr::from([127, 0, 0, 1])));
+        assert_eq!(parse_ip(&[0; 16]), Some(IpAddr::from([0u8; 16])));
+        assert_eq!(parse_ip(&[1, 2, 3]), None);
+    }
+}
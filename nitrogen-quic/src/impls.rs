@@ -5,7 +5,7 @@ use std::{
 };
 
 use async_trait::async_trait;
-use nitrogen_utils::{BiConnect, BiConnnectionAcceptor, BiConnnectionOpener, BiConnnectionSplit, BiListener, BiStreamSplit};
+use nitrogen_utils::{BiConnect, BiConnnectionAcceptor, BiConnnectionOpener, BiConnnectionSplit, BiListener, BiStreamSplit, PeerInfo};
 use s2n_quic::{
     client::Connect,
     connection::{Handle, StreamAcceptor},
@@ -14,7 +14,10 @@ use s2n_quic::{
 };
 use tokio::io::ReadBuf;
 
-use crate::quic::{create_client, create_server};
+use crate::{
+    quic::{create_client, create_client_with_certs, create_server},
+    PeerIdentityContext,
+};
 
 // --- QuicStream ---
 
@@ -89,6 +92,20 @@ impl BiConnnectionSplit for QuicConnection {
         let (opener, acceptor) = self.connection.split();
         (QuicConnectionOpener { opener }, QuicConnectionAcceptor { acceptor })
     }
+
+    /// 对端地址，以及服务端验证过的客户端证书身份
+    fn peer_info(&self) -> PeerInfo {
+        let identity = self
+            .connection
+            .query_event_context(|context: &PeerIdentityContext| context.0.clone())
+            .ok()
+            .flatten();
+
+        PeerInfo {
+            addr: self.connection.remote_addr().ok(),
+            identity,
+        }
+    }
 }
 
 // --- QuicConnectionAcceptor ---
@@ -141,9 +158,8 @@ impl QuicListener {
 impl BiListener for QuicListener {
     type Connection = QuicConnection;
 
-    async fn accept(&mut self) -> anyhow::Result<Self::Connection> {
-        let connection = self.server.accept().await.ok_or(anyhow::anyhow!("no connection"))?;
-        Ok(QuicConnection { connection })
+    async fn accept(&mut self) -> anyhow::Result<Option<Self::Connection>> {
+        Ok(self.server.accept().await.map(|connection| QuicConnection { connection }))
     }
 }
 
@@ -152,12 +168,36 @@ impl BiListener for QuicListener {
 #[derive(Clone)]
 pub struct QuicConnect {
     client: Client,
+    server_name: String,
 }
 
 impl QuicConnect {
     pub async fn bind(addr: std::net::SocketAddr) -> anyhow::Result<Self> {
         let client = create_client(addr).await?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            server_name: "localhost".to_string(),
+        })
+    }
+
+    /// 使用指定的 CA 证书、本端证书与私钥进行 mutual TLS
+    pub async fn bind_with_certs<A, B, C>(addr: std::net::SocketAddr, ca_cert_pem: A, my_cert_pem: B, my_key_pem: C) -> anyhow::Result<Self>
+    where
+        A: AsRef<std::path::Path>,
+        B: AsRef<std::path::Path>,
+        C: AsRef<std::path::Path>,
+    {
+        let client = create_client_with_certs(addr, ca_cert_pem, my_cert_pem, my_key_pem).await?;
+        Ok(Self {
+            client,
+            server_name: "localhost".to_string(),
+        })
+    }
+
+    /// 校验服务端证书时使用的服务器名称，默认为 `localhost`
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.server_name = server_name.into();
+        self
     }
 }
 
@@ -166,7 +206,7 @@ impl BiConnect for QuicConnect {
     type Connection = QuicConnection;
 
     async fn connect(&mut self, addr: std::net::SocketAddr) -> anyhow::Result<Self::Connection> {
-        let connection = self.client.connect(Connect::new(addr).with_server_name("localhost")).await?;
+        let connection = self.client.connect(Connect::new(addr).with_server_name(self.server_name.as_str())).await?;
         Ok(QuicConnection { connection })
     }
 }
@@ -1,5 +1,8 @@
+mod identity;
 mod impls;
 mod mtls;
 mod quic;
 
+pub use identity::*;
 pub use impls::*;
+pub use quic::{CA_CERT_PEM, MY_CERT_PEM, MY_KEY_PEM};
@@ -1,24 +1,40 @@
-use std::{io::Cursor, path::Path};
+use std::{io::Cursor, path::Path, sync::Arc};
 
+use rustls::{
+    crypto::{aws_lc_rs, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer},
+    server::WebPkiClientVerifier,
+};
 use s2n_quic::provider::tls::{
-    default::{rustls, Certificate, Client, PrivateKey, Server},
+    default::{Client, Server},
     Provider,
 };
 
 /// 默认密码套件
 static DEFAULT_CIPHER_SUITES: &[rustls::SupportedCipherSuite] = &[
-    rustls::cipher_suite::TLS13_AES_256_GCM_SHA384,
-    rustls::cipher_suite::TLS13_AES_128_GCM_SHA256,
-    rustls::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
+    aws_lc_rs::cipher_suite::TLS13_AES_256_GCM_SHA384,
+    aws_lc_rs::cipher_suite::TLS13_AES_128_GCM_SHA256,
+    aws_lc_rs::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
 ];
 /// 默认协议版本
 static DEFAULT_PROTOCOL_VERSIONS: &[&rustls::SupportedProtocolVersion] = &[&rustls::version::TLS13];
 
+/// 只启用默认密码套件的加密提供者
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(CryptoProvider {
+        cipher_suites: DEFAULT_CIPHER_SUITES.to_vec(),
+        ..aws_lc_rs::default_provider()
+    })
+}
+
 /// mutual TLS 提供者
+///
+/// 服务端接受任何由 CA 签发的客户端证书，只负责认证；按证书身份限制可调用的服务与方法
+/// 需要在 `nitrogen::Router` 上设置 `AccessPolicy`。
 pub struct MtlsProvider {
     root_store: rustls::RootCertStore,
-    my_cert_chain: Vec<Certificate>,
-    my_private_key: PrivateKey,
+    my_cert_chain: Vec<CertificateDer<'static>>,
+    my_private_key: PrivateKeyDer<'static>,
 }
 
 impl MtlsProvider {
@@ -44,30 +60,30 @@ impl Provider for MtlsProvider {
     type Error = rustls::Error;
 
     fn start_server(self) -> Result<Self::Server, Self::Error> {
-        let mut config = rustls::ServerConfig::builder()
-            .with_cipher_suites(DEFAULT_CIPHER_SUITES)
-            .with_safe_default_kx_groups()
+        let provider = crypto_provider();
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(self.root_store), provider.clone())
+            .build()
+            .map_err(|e| rustls::Error::General(format!("Failed to build client verifier: {}", e)))?;
+        let mut config = rustls::ServerConfig::builder_with_provider(provider)
             .with_protocol_versions(DEFAULT_PROTOCOL_VERSIONS)?
-            .with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(self.root_store).boxed())
+            .with_client_cert_verifier(verifier)
             .with_single_cert(self.my_cert_chain, self.my_private_key)?;
 
         config.ignore_client_order = true;
         config.alpn_protocols = vec![b"plk.1".to_vec()];
 
-        Ok(Server::new(config))
+        Ok(Server::from(config))
     }
 
     fn start_client(self) -> Result<Self::Client, Self::Error> {
-        let mut config = rustls::ClientConfig::builder()
-            .with_cipher_suites(DEFAULT_CIPHER_SUITES)
-            .with_safe_default_kx_groups()
+        let mut config = rustls::ClientConfig::builder_with_provider(crypto_provider())
             .with_protocol_versions(DEFAULT_PROTOCOL_VERSIONS)?
             .with_root_certificates(self.root_store)
             .with_client_auth_cert(self.my_cert_chain, self.my_private_key)?;
 
         config.alpn_protocols = vec![b"plk.1".to_vec()];
 
-        Ok(Client::new(config))
+        Ok(Client::from(config))
     }
 }
 
@@ -78,7 +94,7 @@ async fn into_root_store(path: &Path) -> Result<rustls::RootCertStore, rustls::E
 
     for cert in certs {
         root_store
-            .add(&cert)
+            .add(cert)
             .map_err(|_| rustls::Error::General("Failed to load CA certificate".into()))?;
     }
 
@@ -86,20 +102,26 @@ async fn into_root_store(path: &Path) -> Result<rustls::RootCertStore, rustls::E
 }
 
 /// 获取证书链
-async fn into_certificates(path: &Path) -> Result<Vec<Certificate>, rustls::Error> {
+async fn into_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, rustls::Error> {
     let pemfile = into_pemfile(path).await?;
     let certs = rustls_pemfile::certs(&mut &pemfile[..]).map_err(|_| rustls::Error::General("Failed to load certificate chain".into()))?;
 
-    Ok(certs.into_iter().map(Certificate).collect())
+    Ok(certs.into_iter().map(CertificateDer::from).collect())
 }
 
+type KeyParser = fn(&mut dyn std::io::BufRead) -> std::io::Result<Vec<Vec<u8>>>;
+type KeyWrapper = fn(Vec<u8>) -> PrivateKeyDer<'static>;
+
 /// 获取私钥
-async fn into_private_key(path: &Path) -> Result<PrivateKey, rustls::Error> {
+async fn into_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, rustls::Error> {
     let pemfile = into_pemfile(path).await?;
-    let parsers = [rustls_pemfile::rsa_private_keys, rustls_pemfile::pkcs8_private_keys];
+    let parsers: [(KeyParser, KeyWrapper); 2] = [
+        (rustls_pemfile::rsa_private_keys, |key| PrivatePkcs1KeyDer::from(key).into()),
+        (rustls_pemfile::pkcs8_private_keys, |key| PrivatePkcs8KeyDer::from(key).into()),
+    ];
     let mut cursor = Cursor::new(&pemfile);
 
-    for parser in parsers.iter() {
+    for (parser, wrap) in parsers.iter() {
         cursor.set_position(0);
         match parser(&mut cursor) {
             Ok(ref keys) if keys.is_empty() => continue,
@@ -107,7 +129,7 @@ async fn into_private_key(path: &Path) -> Result<PrivateKey, rustls::Error> {
                 if keys.len() != 1 {
                     return Err(rustls::Error::General("Multiple private keys found".into()));
                 }
-                return Ok(PrivateKey(keys.remove(0)));
+                return Ok(wrap(keys.remove(0)));
             }
             Err(_) => continue,
         }
@@ -1,21 +1,35 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::Path};
 
 use s2n_quic::{provider::event::default::Subscriber, Client, Server};
 
-use crate::mtls::MtlsProvider;
+use crate::{mtls::MtlsProvider, PeerIdentitySubscriber};
 
 pub static CA_CERT_PEM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/ca.crt");
 pub static MY_CERT_PEM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/server.crt");
 pub static MY_KEY_PEM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/server.key");
 
 pub async fn create_client(addr: SocketAddr) -> anyhow::Result<Client> {
-    let mtls = MtlsProvider::new(CA_CERT_PEM, MY_CERT_PEM, MY_KEY_PEM).await?;
+    create_client_with_certs(addr, CA_CERT_PEM, MY_CERT_PEM, MY_KEY_PEM).await
+}
+
+/// 使用指定的 CA 证书、本端证书与私钥创建客户端
+pub async fn create_client_with_certs<A, B, C>(addr: SocketAddr, ca_cert_pem: A, my_cert_pem: B, my_key_pem: C) -> anyhow::Result<Client>
+where
+    A: AsRef<Path>,
+    B: AsRef<Path>,
+    C: AsRef<Path>,
+{
+    let mtls = MtlsProvider::new(ca_cert_pem, my_cert_pem, my_key_pem).await?;
     let client = Client::builder().with_event(Subscriber::default())?.with_tls(mtls)?.with_io(addr)?.start()?;
     Ok(client)
 }
 
 pub async fn create_server(addr: SocketAddr) -> anyhow::Result<Server> {
     let mtls = MtlsProvider::new(CA_CERT_PEM, MY_CERT_PEM, MY_KEY_PEM).await?;
-    let server = Server::builder().with_event(Subscriber::default())?.with_tls(mtls)?.with_io(addr)?.start()?;
+    let server = Server::builder()
+        .with_event((PeerIdentitySubscriber, Subscriber::default()))?
+        .with_tls(mtls)?
+        .with_io(addr)?
+        .start()?;
     Ok(server)
 }
@@ -0,0 +1,152 @@
+use std::{cell::RefCell, fmt, ops::Deref};
+
+use bytes::Bytes;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+thread_local! {
+    static OUTGOING: RefCell<Option<Vec<Bytes>>> = const { RefCell::new(None) };
+    static INCOMING: RefCell<Option<Vec<Bytes>>> = const { RefCell::new(None) };
+}
+
+/// 二进制大对象
+///
+/// 经由 [`crate::encode_message`]（[`crate::FramedMessagePack`] 也以此编码）编码时不参与序列化，而是作为独立的原始帧紧跟在消息帧之后发送，
+/// 消息中只保留它的序号；接收端得到的 [`Bytes`] 直接引用读缓冲区，不再拷贝。
+/// 在其它序列化格式下退化为普通的字节数组。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Blob(pub Bytes);
+
+impl Blob {
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self(bytes.into())
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for Blob {
+    type Target = Bytes;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Blob {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Bytes> for Blob {
+    fn from(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes.into())
+    }
+}
+
+impl From<Blob> for Bytes {
+    fn from(blob: Blob) -> Self {
+        blob.0
+    }
+}
+
+impl Serialize for Blob {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let index = OUTGOING.with(|outgoing| {
+            outgoing.borrow_mut().as_mut().map(|blobs| {
+                blobs.push(self.0.clone());
+                blobs.len() - 1
+            })
+        });
+
+        match index {
+            Some(index) => serializer.serialize_u64(index as u64),
+            None => serializer.serialize_bytes(&self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(BlobVisitor)
+    }
+}
+
+struct BlobVisitor;
+
+impl<'de> de::Visitor<'de> for BlobVisitor {
+    type Value = Blob;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("blob index or byte array")
+    }
+
+    fn visit_u64<E>(self, index: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        INCOMING.with(|incoming| match incoming.borrow().as_ref() {
+            Some(blobs) => blobs
+                .get(index as usize)
+                .cloned()
+                .map(Blob)
+                .ok_or_else(|| E::custom(format!("missing blob #{}", index))),
+            None => Err(E::custom("blob index outside of a framed message")),
+        })
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Blob(Bytes::copy_from_slice(bytes)))
+    }
+
+    fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Blob(bytes.into()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(Blob(bytes.into()))
+    }
+}
+
+/// 执行序列化，并收集期间遇到的所有 [`Blob`]
+pub fn capture_blobs<T>(f: impl FnOnce() -> T) -> (T, Vec<Bytes>) {
+    let previous = OUTGOING.with(|outgoing| outgoing.borrow_mut().replace(vec![]));
+    let output = f();
+    let blobs = OUTGOING.with(|outgoing| std::mem::replace(&mut *outgoing.borrow_mut(), previous));
+    (output, blobs.unwrap_or_default())
+}
+
+/// 执行反序列化，期间 [`Blob`] 的序号从 `blobs` 中取值
+pub fn provide_blobs<T>(blobs: Vec<Bytes>, f: impl FnOnce() -> T) -> T {
+    let previous = INCOMING.with(|incoming| incoming.borrow_mut().replace(blobs));
+    let output = f();
+    INCOMING.with(|incoming| *incoming.borrow_mut() = previous);
+    output
+}
@@ -0,0 +1,103 @@
+use std::io::{Error, ErrorKind, Result};
+
+use serde::{Deserialize, Serialize};
+
+/// 帧压缩算法
+///
+/// 所有变体始终存在，以保证不同 feature 组合编译出的两端线格式一致；
+/// 未启用对应 feature 的算法不会出现在 [`Compression::supported`] 中，也不会被协商选中。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// 本端支持的压缩算法，按优先级排序
+    pub fn supported() -> Vec<Compression> {
+        let mut supported = vec![];
+        if cfg!(feature = "zstd") {
+            supported.push(Compression::Zstd);
+        }
+        if cfg!(feature = "lz4") {
+            supported.push(Compression::Lz4);
+        }
+        supported.push(Compression::None);
+        supported
+    }
+
+    /// 按对端给出的优先级，选出双方都支持的第一个算法
+    pub fn negotiate(offered: &[Compression], accepted: &[Compression]) -> Compression {
+        offered
+            .iter()
+            .copied()
+            .find(|compression| accepted.contains(compression) && compression.is_supported())
+            .unwrap_or(Compression::None)
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Zstd => cfg!(feature = "zstd"),
+            Compression::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    pub(crate) fn flag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    pub(crate) fn from_flag(flag: u8) -> Result<Self> {
+        match flag {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            2 => Ok(Compression::Lz4),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("unknown compression flag: {}", flag))),
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::block::compress_prepend_size(data)),
+            #[allow(unreachable_patterns)]
+            _ => Err(unsupported(self)),
+        }
+    }
+
+    /// 解压缩，解压后的长度超过 `limit` 时返回错误（防止压缩炸弹）
+    pub fn decompress(self, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+        match self {
+            Compression::None if data.len() > limit => Err(too_large(data.len(), limit)),
+            Compression::None => Ok(data.to_vec()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::decompress(data, limit).map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let (size, data) = lz4_flex::block::uncompressed_size(data).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+                if size > limit {
+                    return Err(too_large(size, limit));
+                }
+                lz4_flex::block::decompress(data, size).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(unsupported(self)),
+        }
+    }
+}
+
+fn unsupported(compression: Compression) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("compression {:?} is not enabled", compression))
+}
+
+fn too_large(size: usize, limit: usize) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("decompressed frame too large: {} > {}", size, limit))
+}
@@ -1,11 +1,392 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{Sink, Stream};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_serde::formats::MessagePack;
-use tokio_util::codec::LengthDelimitedCodec;
+use tokio_util::codec::{Decoder, Encoder, Framed, LengthDelimitedCodec};
+
+use crate::{capture_blobs, provide_blobs, Compression};
+
+/// 帧头长度：u32 长度与 1 字节标志位
+const FRAME_HEADER_LENGTH: usize = 5;
+const COMPRESSION_MASK: u8 = 0x0f;
+/// 消息帧后面跟随若干附件帧，`payload` 以 u32 附件数量开头
+const FLAG_ATTACHMENTS: u8 = 0x10;
+/// 附件帧，`payload` 为未压缩的原始字节
+const FLAG_ATTACHMENT: u8 = 0x20;
+/// 超过单帧上限的内容被拆分为多帧，除最后一帧外都带有该标志
+const FLAG_CONTINUATION: u8 = 0x40;
+
+// --- CodecConfig ---
+
+/// 帧编解码配置
+#[derive(Debug, Clone)]
+pub struct CodecConfig {
+    /// 单帧最大长度（含帧头），更大的消息会被拆分为多帧发送
+    pub max_frame_length: usize,
+    /// 单条消息（含附件与各帧的帧头）重组后的最大长度，同时也是解压缩后的长度上限
+    pub max_message_length: usize,
+    /// 愿意使用的压缩算法，按优先级排序
+    pub compression: Vec<Compression>,
+    /// 小于该长度的帧不压缩
+    pub compression_threshold: usize,
+    /// 建立连接时等待握手完成的最长时间
+    pub handshake_timeout: Duration,
+}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_length: 1024 * 1024 * 16,
+            max_message_length: 1024 * 1024 * 256,
+            compression: Compression::supported(),
+            compression_threshold: 1024,
+            handshake_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+// --- Frame ---
+
+/// 一条完整的消息：序列化后的消息体，以及随附的原始附件
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    pub payload: Bytes,
+    pub attachments: Vec<Bytes>,
+}
+
+impl From<Bytes> for Frame {
+    fn from(payload: Bytes) -> Self {
+        Self { payload, attachments: vec![] }
+    }
+}
+
+// --- FrameCodec ---
+
+/// 长度前缀 + 1 字节标志位的帧编解码器
+///
+/// ```text
+/// +----------------+--------+-----------------+
+/// | length: u32 BE | flag   | payload         |
+/// +----------------+--------+-----------------+
+/// ```
+///
+/// `flag` 的低 4 位标识 `payload` 所用的压缩算法；
+/// 带附件的消息帧之后紧跟对应数量的附件帧，附件帧从不压缩，解码时直接引用读缓冲区；
+/// 超过 `max_frame_length` 的消息帧或附件帧被拆分为若干续帧，接收端重组后的总长度受 `max_message_length` 限制。
+#[derive(Debug)]
+pub struct FrameCodec {
+    inner: LengthDelimitedCodec,
+    compression: Compression,
+    compression_threshold: usize,
+    max_message_length: usize,
+    /// 对端能接收的单帧最大长度，发送时按此拆分
+    peer_max_frame_length: usize,
+    /// 正在拆分接收的帧：标志位与已收到的内容
+    partial: Option<(u8, BytesMut)>,
+    /// 已收到消息帧、等待附件帧的消息：消息体、剩余附件数量、已收到的附件
+    pending: Option<(Bytes, usize, Vec<Bytes>)>,
+    /// 当前消息已接收的总长度
+    received: usize,
+}
+
+impl FrameCodec {
+    pub fn new(config: &CodecConfig, compression: Compression) -> Self {
+        Self {
+            inner: LengthDelimitedCodec::builder().max_frame_length(config.max_frame_length).new_codec(),
+            compression,
+            compression_threshold: config.compression_threshold,
+            max_message_length: config.max_message_length,
+            peer_max_frame_length: config.max_frame_length,
+            partial: None,
+            pending: None,
+            received: 0,
+        }
+    }
+
+    /// 设置对端能接收的单帧最大长度（默认与本端相同）
+    pub fn with_peer_max_frame_length(mut self, peer_max_frame_length: usize) -> Self {
+        self.peer_max_frame_length = peer_max_frame_length;
+        self
+    }
+
+    /// 单帧能容纳的内容长度
+    fn chunk_length(&self) -> usize {
+        self.peer_max_frame_length.saturating_sub(1).max(1)
+    }
+
+    /// 内容拆分为帧后在线上占用的长度
+    fn wire_length(&self, length: usize) -> usize {
+        length.div_ceil(self.chunk_length()).max(1) * FRAME_HEADER_LENGTH + length
+    }
+
+    fn encode_frame(&self, flag: u8, header: &[u8], payload: &[u8], dst: &mut BytesMut) -> Result<()> {
+        let chunk_length = self.chunk_length();
+        let mut content = header.chain(payload);
+
+        loop {
+            let length = content.remaining().min(chunk_length);
+            let more = content.remaining() > length;
+
+            dst.reserve(FRAME_HEADER_LENGTH + length);
+            dst.put_u32((1 + length) as u32);
+            dst.put_u8(if more { flag | FLAG_CONTINUATION } else { flag });
+            dst.put((&mut content).take(length));
+
+            if !more {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 读取下一个完整的（可能由多个续帧组成的）帧
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<(u8, BytesMut)>> {
+        loop {
+            let mut frame = match self.inner.decode(src)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            if frame.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidData, "frame without header"));
+            }
+
+            let flag = frame.get_u8();
+
+            // 帧头同样计入，空的附件帧也会消耗配额
+            self.received += FRAME_HEADER_LENGTH + frame.len();
+            if self.received > self.max_message_length {
+                return Err(too_large(self.received, self.max_message_length));
+            }
+
+            let flag = match self.partial.take() {
+                None if flag & FLAG_CONTINUATION == 0 => return Ok(Some((flag, frame))),
+                None => {
+                    self.partial = Some((flag & !FLAG_CONTINUATION, frame));
+                    continue;
+                }
+                Some((partial_flag, mut partial)) => {
+                    if partial_flag != flag & !FLAG_CONTINUATION {
+                        return Err(Error::new(ErrorKind::InvalidData, "continuation frame flag mismatch"));
+                    }
+                    partial.extend_from_slice(&frame);
+                    self.partial = Some((partial_flag, partial));
+                    flag
+                }
+            };
+
+            if flag & FLAG_CONTINUATION == 0 {
+                return Ok(self.partial.take());
+            }
+        }
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new(&CodecConfig::default(), Compression::None)
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        loop {
+            let (flag, mut frame) = match self.decode_frame(src)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            if let Some((_, remaining, attachments)) = &mut self.pending {
+                if flag != FLAG_ATTACHMENT {
+                    return Err(Error::new(ErrorKind::InvalidData, "expected attachment frame"));
+                }
+                attachments.push(frame.freeze());
+                *remaining -= 1;
+            } else {
+                if flag & FLAG_ATTACHMENT != 0 {
+                    return Err(Error::new(ErrorKind::InvalidData, "unexpected attachment frame"));
+                }
+
+                let count = if flag & FLAG_ATTACHMENTS != 0 {
+                    if frame.len() < 4 {
+                        return Err(Error::new(ErrorKind::InvalidData, "truncated attachment header"));
+                    }
+                    let count = frame.get_u32() as usize;
+                    // 每个附件至少占用一个帧头
+                    if count > self.max_message_length / FRAME_HEADER_LENGTH {
+                        return Err(Error::new(ErrorKind::InvalidData, format!("too many attachments: {}", count)));
+                    }
+                    count
+                } else {
+                    0
+                };
+
+                let payload = match Compression::from_flag(flag & COMPRESSION_MASK)? {
+                    Compression::None => frame.freeze(),
+                    compression => {
+                        // 解压后的长度代替压缩后的长度计入，只能用掉消息剩余的配额
+                        let received = self.received - frame.len();
+                        let payload = compression.decompress(&frame, self.max_message_length - received)?;
+                        self.received = received + payload.len();
+                        Bytes::from(payload)
+                    }
+                };
+
+                self.pending = Some((payload, count, Vec::with_capacity(count.min(64))));
+            }
+
+            if let Some((_, 0, _)) = &self.pending {
+                let (payload, _, attachments) = self.pending.take().unwrap();
+                self.received = 0;
+                return Ok(Some(Frame { payload, attachments }));
+            }
+        }
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<()> {
+        let header_length = if item.attachments.is_empty() { 0 } else { 4 };
+        let length =
+            self.wire_length(header_length + item.payload.len()) + item.attachments.iter().map(|attachment| self.wire_length(attachment.len())).sum::<usize>();
+        if length > self.max_message_length {
+            return Err(too_large(length, self.max_message_length));
+        }
+
+        let compressed = match self.compression {
+            Compression::None => None,
+            _ if item.payload.len() < self.compression_threshold => None,
+            compression => Some(compression.compress(&item.payload)?).filter(|compressed| compressed.len() < item.payload.len()),
+        };
+
+        let (mut flag, payload) = match &compressed {
+            Some(compressed) => (self.compression.flag(), &compressed[..]),
+            None => (Compression::None.flag(), &item.payload[..]),
+        };
+
+        if item.attachments.is_empty() {
+            self.encode_frame(flag, &[], payload, dst)?;
+        } else {
+            flag |= FLAG_ATTACHMENTS;
+            self.encode_frame(flag, &(item.attachments.len() as u32).to_be_bytes(), payload, dst)?;
+        }
+
+        for attachment in &item.attachments {
+            self.encode_frame(FLAG_ATTACHMENT, &[], attachment, dst)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Encoder<Bytes> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<()> {
+        self.encode(Frame::from(item), dst)
+    }
+}
+
+fn too_large(length: usize, max_message_length: usize) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("message too large: {} > {}", length, max_message_length))
+}
+
+// --- MessagePack ---
+
+/// 以 MessagePack 序列化消息，消息中的 [`crate::Blob`] 作为附件随帧发送
+pub fn encode_message<T>(item: &T) -> Result<Frame>
+where
+    T: Serialize + ?Sized,
+{
+    let (payload, attachments) = capture_blobs(|| rmp_serde::to_vec(item));
+    let payload = payload.map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+    Ok(Frame {
+        payload: payload.into(),
+        attachments,
+    })
+}
+
+/// 以 MessagePack 反序列化消息，消息中的 [`crate::Blob`] 从附件中取值
+pub fn decode_message<T>(frame: &Frame) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    provide_blobs(frame.attachments.clone(), || rmp_serde::from_slice(&frame.payload)).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+// --- Framed ---
 
-pub type FramedTokioIO<S> = tokio_util::codec::Framed<S, LengthDelimitedCodec>;
+pub type FramedTokioIO<S> = Framed<S, FrameCodec>;
+
+pin_project_lite::pin_project! {
+    /// 在 [`FramedTokioIO`] 之上收发 MessagePack 消息
+    ///
+    /// 单条消息反序列化失败只会产生一个 `Err`，不会中断后续消息的接收。
+    pub struct FramedMessagePack<Item, SinkItem, S> {
+        #[pin]
+        inner: FramedTokioIO<S>,
+        _marker: PhantomData<fn(SinkItem) -> Item>,
+    }
+}
 
-pub type FramedMessagePack<Item, SinkItem, S> = tokio_serde::Framed<FramedTokioIO<S>, Item, SinkItem, MessagePack<Item, SinkItem>>;
+impl<Item, SinkItem, S> Stream for FramedMessagePack<Item, SinkItem, S>
+where
+    Item: DeserializeOwned,
+    S: AsyncRead,
+{
+    type Item = Result<Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project()
+            .inner
+            .poll_next(cx)
+            .map(|frame| frame.map(|frame| frame.and_then(|frame| decode_message(&frame))))
+    }
+}
+
+impl<Item, SinkItem, S> Sink<SinkItem> for FramedMessagePack<Item, SinkItem, S>
+where
+    SinkItem: Serialize,
+    S: AsyncWrite,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<Frame>::poll_ready(self.project().inner, cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<()> {
+        let frame = encode_message(&item)?;
+        self.project().inner.start_send(frame)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<Frame>::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<Frame>::poll_close(self.project().inner, cx)
+    }
+}
+
+pub fn framed_tokio_io<S>(stream: S, codec: FrameCodec) -> FramedTokioIO<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    Framed::new(stream, codec)
+}
 
 pub fn framed_message_pack<Item, SinkItem, S>(framed_io: FramedTokioIO<S>) -> FramedMessagePack<Item, SinkItem, S>
 where
@@ -13,5 +394,269 @@ where
     SinkItem: Serialize + Send + 'static,
     S: AsyncRead + AsyncWrite + Send + 'static,
 {
-    tokio_serde::Framed::new(framed_io, MessagePack::default())
+    FramedMessagePack {
+        inner: framed_io,
+        _marker: PhantomData,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CodecConfig {
+        CodecConfig {
+            max_frame_length: 64,
+            max_message_length: 4096,
+            compression_threshold: 32,
+            ..CodecConfig::default()
+        }
+    }
+
+    /// 不可压缩的内容
+    fn noise(length: usize) -> Bytes {
+        let mut state = 0x2545_f491_u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn encode(codec: &mut FrameCodec, frame: Frame) -> BytesMut {
+        let mut dst = BytesMut::new();
+        codec.encode(frame, &mut dst).unwrap();
+        dst
+    }
+
+    /// 逐字节喂给解码器，覆盖帧头与帧体被拆开读取的情况
+    fn decode(codec: &mut FrameCodec, data: &[u8]) -> Result<Vec<Frame>> {
+        let mut src = BytesMut::new();
+        let mut frames = vec![];
+        for byte in data {
+            src.put_u8(*byte);
+            while let Some(frame) = codec.decode(&mut src)? {
+                frames.push(frame);
+            }
+        }
+        assert!(src.is_empty());
+        Ok(frames)
+    }
+
+    /// 逐帧读取线上的 (长度, 标志位)
+    fn frame_headers(mut data: &[u8]) -> Vec<(usize, u8)> {
+        let mut headers = vec![];
+        while !data.is_empty() {
+            let length = data.get_u32() as usize;
+            headers.push((length, data[0]));
+            data.advance(length);
+        }
+        headers
+    }
+
+    #[test]
+    fn round_trip_below_and_above_compression_threshold() {
+        for compression in Compression::supported() {
+            let mut codec = FrameCodec::new(&config(), compression);
+
+            for (payload, compressed) in [
+                (Bytes::from_static(b"short"), false),
+                (Bytes::from(vec![b'a'; 1000]), compression != Compression::None),
+            ] {
+                let dst = encode(&mut codec, Frame::from(payload.clone()));
+                let expected = if compressed { compression.flag() } else { Compression::None.flag() };
+                assert_eq!(dst[4] & COMPRESSION_MASK, expected, "{:?}", compression);
+
+                let frames = decode(&mut codec, &dst).unwrap();
+                assert_eq!(frames.len(), 1);
+                assert_eq!(frames[0].payload, payload);
+                assert!(frames[0].attachments.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn splits_messages_larger_than_max_frame_length() {
+        for compression in Compression::supported() {
+            let mut codec = FrameCodec::new(&config(), compression);
+            let frame = Frame {
+                payload: noise(1000),
+                attachments: vec![noise(300), Bytes::new(), Bytes::from_static(b"tail")],
+            };
+
+            let dst = encode(&mut codec, frame.clone());
+            let headers = frame_headers(&dst);
+            assert!(headers.len() > 20, "{:?}", compression);
+            assert!(headers.iter().all(|(length, _)| *length <= 64));
+            assert!(headers.iter().any(|(_, flag)| flag & FLAG_CONTINUATION != 0));
+
+            let frames = decode(&mut codec, &dst).unwrap();
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].payload, frame.payload);
+            assert_eq!(frames[0].attachments, frame.attachments);
+
+            // 同一个解码器可以继续接收下一条消息
+            let dst = encode(&mut codec, Frame::from(Bytes::from_static(b"next")));
+            assert_eq!(decode(&mut codec, &dst).unwrap()[0].payload, Bytes::from_static(b"next"));
+        }
+    }
+
+    #[test]
+    fn rejects_messages_over_max_message_length() {
+        let permissive = CodecConfig {
+            max_message_length: 1024 * 1024,
+            ..config()
+        };
+
+        for compression in Compression::supported() {
+            let mut codec = FrameCodec::new(&config(), compression);
+            let mut dst = BytesMut::new();
+            let err = codec.encode(Frame::from(noise(5000)), &mut dst).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+            // 附件计入消息长度
+            let frame = Frame {
+                payload: noise(100),
+                attachments: vec![noise(2000), noise(2000)],
+            };
+            assert!(codec.encode(frame.clone(), &mut dst).is_err());
+
+            let mut sender = FrameCodec::new(&permissive, compression);
+            for frame in [Frame::from(noise(5000)), frame] {
+                let dst = encode(&mut sender, frame);
+                let err = decode(&mut FrameCodec::new(&config(), compression), &dst).unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::InvalidData);
+                assert!(err.to_string().contains("too large"), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_frame_headers_toward_message_length() {
+        let max_message_length = config().max_message_length;
+
+        // 空附件在编码端与解码端都按帧头计入
+        let frame = Frame {
+            payload: Bytes::new(),
+            attachments: vec![Bytes::new(); 100],
+        };
+        let dst = encode(&mut FrameCodec::default(), frame.clone());
+        assert_eq!(
+            decode(&mut FrameCodec::new(&config(), Compression::None), &dst).unwrap()[0].attachments.len(),
+            100
+        );
+
+        let frame = Frame {
+            payload: Bytes::new(),
+            attachments: vec![Bytes::new(); max_message_length / FRAME_HEADER_LENGTH],
+        };
+        assert!(FrameCodec::new(&config(), Compression::None)
+            .encode(frame.clone(), &mut BytesMut::new())
+            .is_err());
+        let dst = encode(&mut FrameCodec::default(), frame);
+        let err = decode(&mut FrameCodec::new(&config(), Compression::None), &dst).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+
+        // 附件数量在收到附件帧之前就被拒绝
+        let mut dst = BytesMut::new();
+        dst.put_u32(5);
+        dst.put_u8(FLAG_ATTACHMENTS);
+        dst.put_u32(u32::MAX);
+        let err = FrameCodec::new(&config(), Compression::None).decode(&mut dst).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("too many attachments"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_decompression_bomb() {
+        let permissive = CodecConfig {
+            max_frame_length: 1024 * 1024,
+            max_message_length: 16 * 1024 * 1024,
+            ..config()
+        };
+        let strict = CodecConfig {
+            max_message_length: 64 * 1024,
+            ..config()
+        };
+
+        for compression in Compression::supported().into_iter().filter(|compression| *compression != Compression::None) {
+            let dst = encode(
+                &mut FrameCodec::new(&permissive, compression),
+                Frame::from(Bytes::from(vec![0; 8 * 1024 * 1024])),
+            );
+            // 压缩后的帧远小于接收端的限制，只有解压后才会超出
+            assert!(dst.len() < strict.max_message_length, "{:?}: {}", compression, dst.len());
+
+            let err = decode(&mut FrameCodec::new(&strict, compression), &dst).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn counts_decompressed_length_toward_message_length() {
+        let permissive = CodecConfig {
+            max_message_length: 16 * 1024,
+            ..config()
+        };
+
+        for compression in Compression::supported().into_iter().filter(|compression| *compression != Compression::None) {
+            // 单独看压缩后的线上长度与解压后的消息都不超过限制，加上附件后超出
+            let frame = Frame {
+                payload: Bytes::from(vec![0; 3000]),
+                attachments: vec![noise(2000)],
+            };
+            let dst = encode(&mut FrameCodec::new(&permissive, compression), frame);
+            assert!(dst.len() < config().max_message_length, "{:?}: {}", compression, dst.len());
+
+            let err = decode(&mut FrameCodec::new(&config(), compression), &dst).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn blob_round_trip_without_copy() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Upload {
+            name: String,
+            data: crate::Blob,
+        }
+
+        let upload = Upload {
+            name: "a.bin".into(),
+            data: crate::Blob::new(noise(40)),
+        };
+        let frame = encode_message(&upload).unwrap();
+        assert_eq!(frame.attachments.len(), 1);
+        assert!(frame.payload.len() < 40);
+
+        for compression in Compression::supported() {
+            let mut codec = FrameCodec::new(&config(), compression);
+            let mut src = encode(&mut codec, frame.clone());
+            let buffer = src.as_ptr_range();
+
+            let received = codec.decode(&mut src).unwrap().unwrap();
+            let decoded: Upload = decode_message(&received).unwrap();
+            assert_eq!(decoded, upload);
+            // 附件直接引用读缓冲区
+            assert!(buffer.contains(&decoded.data.as_ptr()), "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn rejects_continuation_flag_mismatch() {
+        let mut dst = BytesMut::new();
+        dst.put_u32(4);
+        dst.put_u8(FLAG_CONTINUATION);
+        dst.put_slice(b"abc");
+        dst.put_u32(4);
+        dst.put_u8(FLAG_ATTACHMENT);
+        dst.put_slice(b"def");
+
+        let err = decode(&mut FrameCodec::new(&config(), Compression::None), &dst).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("continuation frame flag mismatch"), "{}", err);
+    }
 }
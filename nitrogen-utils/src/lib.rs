@@ -1,5 +1,7 @@
+mod blob;
 mod channel;
+mod compression;
 mod framed;
 mod network;
 
-pub use {channel::*, framed::*, network::*};
+pub use {blob::*, channel::*, compression::*, framed::*, network::*};
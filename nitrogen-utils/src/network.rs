@@ -10,7 +10,8 @@ where
 {
     type Connection: BiConnnectionSplit;
 
-    async fn accept(&mut self) -> anyhow::Result<Self::Connection>;
+    /// 监听关闭后返回 `None`；单个连接建立失败时返回错误，之后仍可继续接受
+    async fn accept(&mut self) -> anyhow::Result<Option<Self::Connection>>;
 }
 
 #[async_trait]
@@ -39,6 +40,34 @@ pub trait BiConnnectionSplit {
     type Acceptor: BiConnnectionAcceptor;
 
     fn split(self) -> (Self::Opener, Self::Acceptor);
+
+    /// 对端的地址与身份，传输层不提供时为空
+    fn peer_info(&self) -> PeerInfo {
+        PeerInfo::default()
+    }
+}
+
+/// 连接对端的信息，由传输层在建立连接时提供
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub addr: Option<SocketAddr>,
+    /// 经过 mutual TLS 验证的对端证书
+    pub identity: Option<PeerIdentity>,
+}
+
+/// 对端证书中的身份
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PeerIdentity {
+    /// 证书主题，如 `CN=client, O=acme`
+    pub subject: String,
+    /// 主题备用名称，如 `DNS:client.example.com`、`URI:spiffe://acme/client`、`IP:10.0.0.1`
+    pub sans: Vec<String>,
+    /// 主题中的 CN
+    pub common_name: Option<String>,
+    /// 主题中的 OU，可以有多个
+    pub organizational_units: Vec<String>,
+    /// 证书 DER 编码的 SHA-256，小写十六进制
+    pub fingerprint: String,
 }
 
 pub trait BiStreamSplit {
@@ -47,3 +76,10 @@ pub trait BiStreamSplit {
 
     fn split(self) -> (Self::Write, Self::Read);
 }
+
+/// 任意可读写的双向流，用于擦除具体的流类型
+pub trait BiStream: AsyncRead + AsyncWrite + Send {}
+
+impl<T> BiStream for T where T: AsyncRead + AsyncWrite + Send {}
+
+pub type BoxedBiStream = std::pin::Pin<Box<dyn BiStream>>;
@@ -0,0 +1,369 @@
+use std::path::Path;
+
+use nitrogen_utils::PeerIdentity;
+use serde::{Deserialize, Serialize};
+
+// --- Principal ---
+
+/// 按客户端证书身份匹配调用方，设置的条件都满足时匹配
+///
+/// 所有条件都为空时匹配任何调用方，包括没有出示证书的调用方。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Principal {
+    /// 主题中的 CN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cn: Option<String>,
+    /// 任一主题备用名称，带类型前缀，如 `DNS:client.example.com`、`URI:spiffe://acme/client`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub san: Option<String>,
+    /// 任一 OU
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ou: Option<String>,
+    /// 证书的 SHA-256 指纹，十六进制，不区分大小写，可以带 `:` 分隔
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+impl Principal {
+    /// 任何调用方
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    pub fn cn(cn: impl Into<String>) -> Self {
+        Self {
+            cn: Some(cn.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn san(san: impl Into<String>) -> Self {
+        Self {
+            san: Some(san.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn ou(ou: impl Into<String>) -> Self {
+        Self {
+            ou: Some(ou.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn fingerprint(fingerprint: impl Into<String>) -> Self {
+        Self {
+            fingerprint: Some(fingerprint.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, identity: Option<&PeerIdentity>) -> bool {
+        if *self == Self::any() {
+            return true;
+        }
+        let Some(identity) = identity else {
+            return false;
+        };
+
+        condition(&self.cn, |cn| identity.common_name.as_ref() == Some(cn))
+            && condition(&self.san, |san| identity.sans.contains(san))
+            && condition(&self.ou, |ou| identity.organizational_units.contains(ou))
+            && condition(&self.fingerprint, |fingerprint| {
+                normalize_fingerprint(fingerprint) == normalize_fingerprint(&identity.fingerprint)
+            })
+    }
+}
+
+/// 未设置的条件视为满足
+fn condition(expected: &Option<String>, matches: impl FnOnce(&String) -> bool) -> bool {
+    match expected {
+        Some(expected) => matches(expected),
+        None => true,
+    }
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.chars().filter(|c| *c != ':').map(|c| c.to_ascii_lowercase()).collect()
+}
+
+// --- AccessPolicy ---
+
+/// 一条授权规则：允许匹配 `principal` 的调用方调用 `allow` 中的方法
+///
+/// 在 JSON 中条件与 `allow` 写在同一层；不认识的键是错误，匹配任何调用方的规则需要写明 `"principal": "any"`，
+/// 以免拼错的条件（如 `"CN"`）被忽略后规则变成对所有人开放。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RuleRepr", into = "RuleRepr")]
+pub struct AccessRule {
+    pub principal: Principal,
+    /// `*`、`Service/*` 或 `Service/method`，服务与方法都使用线上名称
+    pub allow: Vec<String>,
+}
+
+impl AccessRule {
+    fn allows(&self, service: &str, method: &str) -> bool {
+        self.allow.iter().any(|target| {
+            target == "*"
+                // 服务名可以带 `/`，方法名不能
+                || match target.rsplit_once('/') {
+                    Some((s, m)) => s == service && (m == "*" || m == method),
+                    None => false,
+                }
+        })
+    }
+}
+
+/// [`AccessRule`] 在 JSON 中的形式
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    principal: Option<AnyPrincipal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    san: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ou: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    allow: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AnyPrincipal {
+    Any,
+}
+
+impl TryFrom<RuleRepr> for AccessRule {
+    type Error = String;
+
+    fn try_from(repr: RuleRepr) -> Result<Self, Self::Error> {
+        let principal = Principal {
+            cn: repr.cn,
+            san: repr.san,
+            ou: repr.ou,
+            fingerprint: repr.fingerprint,
+        };
+        match (repr.principal, principal == Principal::any()) {
+            (Some(AnyPrincipal::Any), false) => Err("`\"principal\": \"any\"` cannot be combined with cn, san, ou or fingerprint".into()),
+            (None, true) => Err("rule without cn, san, ou or fingerprint must set `\"principal\": \"any\"`".into()),
+            _ => Ok(AccessRule { principal, allow: repr.allow }),
+        }
+    }
+}
+
+impl From<AccessRule> for RuleRepr {
+    fn from(rule: AccessRule) -> Self {
+        let any = rule.principal == Principal::any();
+        Self {
+            principal: any.then_some(AnyPrincipal::Any),
+            cn: rule.principal.cn,
+            san: rule.principal.san,
+            ou: rule.principal.ou,
+            fingerprint: rule.principal.fingerprint,
+            allow: rule.allow,
+        }
+    }
+}
+
+/// 按客户端证书身份授权调用服务与方法
+///
+/// 默认拒绝，只要有一条规则同时匹配调用方与方法即允许。
+/// 通过 [`crate::ServiceOptions::access_policy`] 或 [`crate::Router::set_access_policy`] 在服务端分发请求时检查，
+/// 被拒绝的请求回复 [`crate::ErrorKind::PermissionDenied`]。
+///
+/// ```ignore
+/// let policy = AccessPolicy::new()
+///     .allow(Principal::cn("billing"), ["Billing/*", "Storage/get"])
+///     .allow(Principal::ou("ops"), ["*"])
+///     .allow(Principal::any(), ["Reflection/*"]);
+/// ```
+///
+/// 也可以从 JSON 策略文件加载，每条规则的条件与 [`Principal`] 的字段相同，不认识的键是错误：
+///
+/// ```json
+/// {
+///   "rules": [
+///     { "cn": "billing", "allow": ["Billing/*", "Storage/get"] },
+///     { "ou": "ops", "allow": ["*"] },
+///     { "fingerprint": "3f:a1:...", "allow": ["Storage/*"] },
+///     { "principal": "any", "allow": ["Reflection/*"] }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessPolicy {
+    pub rules: Vec<AccessRule>,
+}
+
+impl AccessPolicy {
+    /// 拒绝所有调用的空策略
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow<I, S>(mut self, principal: Principal, targets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.rules.push(AccessRule {
+            principal,
+            allow: targets.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// 读取 JSON 策略文件
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("read access policy {}: {}", path.display(), err))?;
+        Self::from_json(&json).map_err(|err| anyhow::anyhow!("parse access policy {}: {}", path.display(), err))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("AccessPolicy is always serializable")
+    }
+
+    /// `identity` 是否可以调用服务 `service` 的方法 `method`
+    pub fn is_allowed(&self, identity: Option<&PeerIdentity>, service: &str, method: &str) -> bool {
+        self.rules.iter().any(|rule| rule.allows(service, method) && rule.principal.matches(identity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> PeerIdentity {
+        PeerIdentity {
+            subject: "CN=billing, OU=finance, OU=ops".into(),
+            sans: vec!["DNS:billing.internal".into(), "URI:spiffe://acme/billing".into()],
+            common_name: Some("billing".into()),
+            organizational_units: vec!["finance".into(), "ops".into()],
+            fingerprint: "3fa1b2".into(),
+        }
+    }
+
+    #[test]
+    fn matches_principal_conditions() {
+        let identity = identity();
+        let matches = |principal: Principal| principal.matches(Some(&identity));
+
+        assert!(matches(Principal::cn("billing")));
+        assert!(!matches(Principal::cn("storage")));
+        assert!(matches(Principal::san("URI:spiffe://acme/billing")));
+        assert!(!matches(Principal::san("billing.internal")));
+        assert!(matches(Principal::ou("ops")));
+        assert!(!matches(Principal::ou("dev")));
+        assert!(matches(Principal::fingerprint("3F:A1:B2")));
+        assert!(!matches(Principal::fingerprint("3f:a1")));
+
+        // 所有条件都需要满足
+        let principal = Principal {
+            ou: Some("ops".into()),
+            ..Principal::cn("billing")
+        };
+        assert!(matches(principal));
+        let principal = Principal {
+            ou: Some("dev".into()),
+            ..Principal::cn("billing")
+        };
+        assert!(!matches(principal));
+    }
+
+    #[test]
+    fn anonymous_callers_only_match_any() {
+        assert!(Principal::any().matches(None));
+        assert!(Principal::any().matches(Some(&identity())));
+        assert!(!Principal::cn("billing").matches(None));
+        assert!(!Principal::fingerprint("").matches(None));
+    }
+
+    #[test]
+    fn matches_targets() {
+        let policy = AccessPolicy::new()
+            .allow(Principal::cn("billing"), ["Billing/*", "Storage/get"])
+            .allow(Principal::ou("ops"), ["*"])
+            .allow(Principal::any(), ["Reflection/*"]);
+
+        let billing = PeerIdentity {
+            organizational_units: vec![],
+            ..identity()
+        };
+        assert!(policy.is_allowed(Some(&billing), "Billing", "charge"));
+        assert!(policy.is_allowed(Some(&billing), "Storage", "get"));
+        assert!(!policy.is_allowed(Some(&billing), "Storage", "put"));
+        assert!(!policy.is_allowed(Some(&billing), "BillingV2", "charge"));
+
+        let ops = PeerIdentity {
+            common_name: Some("deploy".into()),
+            ..identity()
+        };
+        assert!(policy.is_allowed(Some(&ops), "Storage", "put"));
+
+        assert!(policy.is_allowed(None, "Reflection", "list_services"));
+        assert!(!policy.is_allowed(None, "Billing", "charge"));
+
+        // 只有服务名的目标不匹配任何方法，空策略拒绝所有调用
+        let policy = AccessPolicy::new().allow(Principal::any(), ["Billing"]);
+        assert!(!policy.is_allowed(None, "Billing", "charge"));
+        assert!(!AccessPolicy::new().is_allowed(Some(&identity()), "Billing", "charge"));
+
+        // 方法名从最后一个 `/` 处分开，服务名可以带 `/`
+        let policy = AccessPolicy::new().allow(Principal::any(), ["acme/Billing/charge", "acme/Storage/*"]);
+        assert!(policy.is_allowed(None, "acme/Billing", "charge"));
+        assert!(policy.is_allowed(None, "acme/Storage", "get"));
+        assert!(!policy.is_allowed(None, "acme", "Billing/charge"));
+    }
+
+    #[test]
+    fn loads_json_rules() {
+        let policy = AccessPolicy::from_json(
+            r#"{
+                "rules": [
+                    { "cn": "billing", "ou": "finance", "allow": ["Billing/*"] },
+                    { "principal": "any", "allow": ["Reflection/*"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let expected = AccessPolicy::new()
+            .allow(
+                Principal {
+                    ou: Some("finance".into()),
+                    ..Principal::cn("billing")
+                },
+                ["Billing/*"],
+            )
+            .allow(Principal::any(), ["Reflection/*"]);
+        assert_eq!(policy, expected);
+        assert_eq!(AccessPolicy::from_json(&policy.to_json()).unwrap(), policy);
+    }
+
+    #[test]
+    fn rejects_ambiguous_json_rules() {
+        for json in [
+            // 拼错的条件
+            r#"{ "rules": [{ "CN": "billing", "allow": ["*"] }] }"#,
+            r#"{ "rules": [{ "cn": "billing", "alow": ["*"] }] }"#,
+            // 没有条件也没有写明 any
+            r#"{ "rules": [{ "allow": ["*"] }] }"#,
+            // any 与条件同时出现
+            r#"{ "rules": [{ "principal": "any", "cn": "billing", "allow": ["*"] }] }"#,
+            r#"{ "rules": [{ "principal": "all", "allow": ["*"] }] }"#,
+            r#"{ "rules": [{ "cn": "billing" }] }"#,
+        ] {
+            assert!(AccessPolicy::from_json(json).is_err(), "{}", json);
+        }
+    }
+}
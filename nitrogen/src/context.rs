@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use nitrogen_utils::{PeerIdentity, PeerInfo};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+
+use crate::factory::ConnectionInstances;
+
+/// 请求附带的元数据，客户端通过 `*Client::with_metadata` 设置
+pub type Metadata = BTreeMap<String, String>;
+
+/// 客户端与服务端都支持时才在请求信封中发送超时与元数据
+pub const CONTEXT_FEATURE: &str = "context";
+
+tokio::task_local! {
+    static CURRENT: Context;
+}
+
+// --- ConnectionInfo ---
+
+/// 一个连接的信息，由该连接上的所有流共享
+#[derive(Debug, Default)]
+pub struct ConnectionInfo {
+    id: u64,
+    peer: PeerInfo,
+    pub(crate) instances: ConnectionInstances,
+}
+
+impl ConnectionInfo {
+    /// 分配一个在进程内唯一的连接 id
+    pub fn new(peer: PeerInfo) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            peer,
+            instances: ConnectionInstances::default(),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn peer(&self) -> &PeerInfo {
+        &self.peer
+    }
+
+    /// 连接上的所有流结束后调用，运行按连接创建的服务实例的 `on_disconnect`
+    ///
+    /// [`crate::Router`] 会自行调用；自己构造连接信息并交给 `serve_stream_with_context` 时需要调用。
+    pub async fn close(&self) {
+        self.instances.close().await
+    }
+}
+
+// --- Context ---
+
+/// 服务端处理一个请求时的上下文
+///
+/// trait 方法可以声明 `ctx: &nitrogen::Context` 参数来获取，该参数不出现在请求与客户端方法中；
+/// 也可以在处理请求的任务中通过 [`Context::current`] 获取。
+///
+/// ```ignore
+/// #[nitrogen::rpc_service]
+/// pub trait Storage {
+///     async fn get(&self, ctx: &nitrogen::Context, key: String) -> Option<Vec<u8>>;
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    connection: Arc<ConnectionInfo>,
+    stream_id: u64,
+    request_id: u64,
+    metadata: Arc<Metadata>,
+    deadline: Option<Instant>,
+    cancellation: CancellationToken,
+}
+
+impl Context {
+    /// 连接上第 `stream_id` 条流的上下文，流上的请求由它派生
+    pub fn for_stream(connection: Arc<ConnectionInfo>, stream_id: u64) -> Self {
+        Self {
+            connection,
+            stream_id,
+            ..Default::default()
+        }
+    }
+
+    /// 不知道对端信息的单条流，如直接在流上 `serve` 的服务
+    pub(crate) fn detached() -> Self {
+        Self::for_stream(Arc::new(ConnectionInfo::new(PeerInfo::default())), 0)
+    }
+
+    /// 派生一个请求的上下文，流关闭或超过 `timeout` 时取消
+    pub(crate) fn for_request(&self, request_id: u64, metadata: Metadata, timeout: Option<Duration>) -> Self {
+        Self {
+            connection: self.connection.clone(),
+            stream_id: self.stream_id,
+            request_id,
+            metadata: Arc::new(metadata),
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            cancellation: self.cancellation.child_token(),
+        }
+    }
+
+    /// 当前任务正在处理的请求的上下文
+    ///
+    /// 只在处理请求的任务中可用，处理函数另外 spawn 的任务中需要把上下文传过去。
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// 以该上下文运行 `future`，并在超过截止时间时取消
+    ///
+    /// 取消只是通知，`future` 仍会运行到结束。
+    pub(crate) async fn run<F>(self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        let deadline = self.deadline;
+        let cancellation = self.cancellation.clone();
+        let future = CURRENT.scope(self, future);
+        tokio::pin!(future);
+
+        if let Some(deadline) = deadline {
+            tokio::select! {
+                output = &mut future => return output,
+                _ = tokio::time::sleep_until(deadline.into()) => cancellation.cancel(),
+            }
+        }
+
+        future.await
+    }
+
+    pub fn connection(&self) -> &ConnectionInfo {
+        &self.connection
+    }
+
+    pub fn connection_id(&self) -> u64 {
+        self.connection.id
+    }
+
+    /// 流在连接中的序号，从 0 开始
+    pub fn stream_id(&self) -> u64 {
+        self.stream_id
+    }
+
+    /// 请求信封中的 id，在一条流上唯一
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.connection.peer.addr
+    }
+
+    /// 经过 mutual TLS 验证的客户端证书身份
+    pub fn peer_identity(&self) -> Option<&PeerIdentity> {
+        self.connection.peer.identity.as_ref()
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// 客户端放弃等待的时间，客户端没有告知超时时为空
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// 距离截止时间的剩余时间，已经超时时为 0
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// 超过截止时间或流关闭时取消
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    pub fn cancelled(&self) -> WaitForCancellationFuture<'_> {
+        self.cancellation.cancelled()
+    }
+}
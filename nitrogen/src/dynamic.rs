@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use nitrogen_utils::{decode_message, encode_message};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use rmpv::Value;
+
+use crate::{CallOptions, Context, Error, ErrorKind, Message, Metadata, MethodKey, Result, Service, Session, CONTEXT_FEATURE};
+
+// --- DynamicRequest / DynamicResponse ---
+
+// DynamicRequest 与 DynamicResponse 通过 rpc_service 自动实现
+
+/// 由方法的线上名称与按位置排列的参数构造请求
+///
+/// 参数的解码方式与线上相同：缺少的末尾参数取 `#[rpc(default)]` 给出的默认值，多余的末尾参数被忽略，
+/// 结构体参数可以是数组或以字段名为键的表。
+pub trait DynamicRequest: Sized {
+    fn from_dynamic(method: &str, args: Vec<Value>) -> Result<Self>;
+}
+
+/// 取出响应中的返回值，结构体编码为数组，与线上相同
+pub trait DynamicResponse {
+    fn into_dynamic(self) -> Result<Value>;
+}
+
+/// 以 `[method_key, [args...]]` 解码请求
+#[doc(hidden)]
+pub fn decode_dynamic_request<Req>(service: &str, method: &str, args: Vec<Value>) -> Result<Req>
+where
+    Req: DeserializeOwned,
+{
+    let key = match method_key(method) {
+        MethodKey::Id(id) => Value::from(id),
+        MethodKey::Name(_) => Value::from(method),
+    };
+    rmpv::ext::from_value(Value::Array(vec![key, Value::Array(args)]))
+        .map_err(|err| Error::new(ErrorKind::InvalidRequest, format!("{}::{} decode error: {}", service, method, err)))
+}
+
+#[doc(hidden)]
+pub fn encode_dynamic_response<T>(service: &str, method: &str, result: Result<T>) -> Result<Value>
+where
+    T: Serialize,
+{
+    rmpv::ext::to_value(result?).map_err(|err| Error::new(ErrorKind::InvalidResponse, format!("{}::{} encode error: {}", service, method, err)))
+}
+
+/// 数字按方法 id 查找，其余按名称
+fn method_key(method: &str) -> MethodKey {
+    match method.parse::<u32>() {
+        Ok(id) => MethodKey::Id(id),
+        Err(_) => MethodKey::Name(method.to_string().into()),
+    }
+}
+
+// --- 服务端 ---
+
+impl Service {
+    /// 在进程内按方法的线上名称与按位置排列的参数调用，用于网关等没有生成类型的调用方
+    ///
+    /// 请求与响应各编解码一次，与远程调用经过同样的解码与分发。
+    /// 工厂创建的服务为这次调用单独创建实例，返回前运行 `on_disconnect`。
+    pub async fn call_dynamic(&self, method: &str, args: Vec<Value>, options: &CallOptions) -> Result<Value> {
+        let name = self.name();
+        let ctx = Context::detached();
+        let (service, disconnect) = self
+            .bind(&ctx)
+            .await
+            .map_err(|err| Error::new(ErrorKind::Other, format!("{}::{} connect error: {}", name, method, err)))?;
+
+        let mut message = Message::new(0, (method_key(method), args));
+        message.timeout = Some(options.timeout.as_millis() as u64);
+        message.metadata = options.metadata.clone();
+        let frame = encode_message(&message).map_err(|err| Error::new(ErrorKind::InvalidRequest, format!("{}::{} encode error: {}", name, method, err)))?;
+
+        let response = tokio::time::timeout(options.timeout, service.call(&ctx, 0, &frame)).await;
+        if let Some(disconnect) = disconnect {
+            disconnect.await;
+        }
+        ctx.connection().close().await;
+
+        match response {
+            Ok(Some(frame)) => decode_dynamic_response(name, method, &frame),
+            Ok(None) => Err(Error::new(ErrorKind::InvalidResponse, format!("{}::{} response encode error", name, method))),
+            Err(err) => Err(Error::new(ErrorKind::Timeout, format!("{}::{} timeout error: {}", name, method, err))),
+        }
+    }
+}
+
+fn decode_dynamic_response(service: &str, method: &str, frame: &nitrogen_utils::Frame) -> Result<Value> {
+    decode_message::<Message<Result<Value>>>(frame)
+        .map_err(|err| Error::new(ErrorKind::InvalidResponse, format!("{}::{} decode error: {}", service, method, err)))?
+        .payload
+}
+
+// --- DynamicClient ---
+
+/// 不依赖生成类型的客户端，以服务名、方法的线上名称与 [`Value`] 参数调用
+///
+/// 参数按位置传递，返回值不按类型解码；用于命令行工具、网关等只在运行时知道服务的场合。
+/// 服务需要在会话握手时请求：
+///
+/// ```ignore
+/// let session = nitrogen::Session::connect(stream, &["MyService"], CodecConfig::default()).await?;
+/// let client = nitrogen::DynamicClient::with_session(&session);
+/// let value = client.call("MyService", "fn_name", vec![1.into(), "name".into()]).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct DynamicClient {
+    session: Session,
+    options: CallOptions,
+}
+
+impl DynamicClient {
+    pub fn with_session(session: &Session) -> Self {
+        Self {
+            session: session.clone(),
+            options: CallOptions::default(),
+        }
+    }
+
+    /// 等待响应的时间，默认为 5 秒
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// 随每个请求发送的元数据
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.options.metadata = metadata;
+        self
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// `method` 为方法的线上名称，指定了 `#[rpc(id = N)]` 的方法也可以传入数字 id
+    pub async fn call(&self, service: &str, method: &str, args: Vec<Value>) -> Result<Value> {
+        match tokio::time::timeout(self.options.timeout, self.request(service, method, args)).await {
+            Ok(result) => result,
+            Err(err) => Err(Error::new(ErrorKind::Timeout, format!("{}::{} timeout error: {}", service, method, err))),
+        }
+    }
+
+    /// 参数与返回值以 JSON 表示；二进制数据表示为数字数组
+    pub async fn call_json(&self, service: &str, method: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value> {
+        let args = args
+            .iter()
+            .map(rmpv::ext::to_value)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| Error::new(ErrorKind::InvalidRequest, format!("{}::{} encode error: {}", service, method, err)))?;
+        let value = self.call(service, method, args).await?;
+        serde_json::to_value(value).map_err(|err| Error::new(ErrorKind::InvalidResponse, format!("{}::{} decode error: {}", service, method, err)))
+    }
+
+    async fn request(&self, service: &str, method: &str, args: Vec<Value>) -> Result<Value> {
+        let index = self.session.service(service).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UnknownService,
+                format!("DynamicClient::call error: service {} is not requested by the session", service),
+            )
+        })?;
+
+        let id = self.session.next_id();
+        let mut message = Message::new(id, (method_key(method), args)).with_service(index);
+        if self.session.has_feature(CONTEXT_FEATURE).await {
+            message.timeout = Some(self.options.timeout.as_millis() as u64);
+            message.metadata = self.options.metadata.clone();
+        }
+        let frame = encode_message(&message).map_err(|err| Error::new(ErrorKind::InvalidRequest, format!("{}::{} encode error: {}", service, method, err)))?;
+
+        let frame = self.session.call(index, id, frame).await?;
+        decode_dynamic_response(service, method, &frame)
+    }
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use tokio::sync::OnceCell;
+
+use crate::{Context, Service};
+
+// --- ServiceFactory ---
+
+/// 工厂创建的服务实例的生命周期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ServiceScope {
+    /// 每个连接一个实例，由连接上请求该服务的所有流共享，连接关闭且所有流结束后调用 `on_disconnect`
+    #[default]
+    Connection,
+    /// 每条流（即每个客户端会话）一个实例，流结束后调用 `on_disconnect`
+    Stream,
+}
+
+/// 为每个连接或流创建服务实例，会话状态（如已登录的用户、订阅列表）保存在实例上
+///
+/// 由 `MyServiceExt::from_factory` 转换为服务，注册到 [`crate::Router`]；
+/// 闭包 `Fn(&Context) -> T` 也实现了该 trait。
+///
+/// ```ignore
+/// struct Sessions;
+///
+/// impl nitrogen::ServiceFactory for Sessions {
+///     type Service = ChatImpl;
+///
+///     async fn on_connect(&self, ctx: &nitrogen::Context) -> anyhow::Result<ChatImpl> {
+///         Ok(ChatImpl::new(ctx.peer_identity().cloned()))
+///     }
+///
+///     async fn on_disconnect(&self, chat: &ChatImpl, _ctx: &nitrogen::Context) {
+///         chat.unsubscribe_all().await;
+///     }
+/// }
+///
+/// router.add(ChatExt::from_factory(Sessions).with_scope(nitrogen::ServiceScope::Stream));
+/// ```
+pub trait ServiceFactory: Send + Sync + 'static {
+    type Service: Send + Sync + 'static;
+
+    /// 客户端在握手中请求该服务时创建实例，返回错误时拒绝握手
+    fn on_connect(&self, ctx: &Context) -> impl Future<Output = anyhow::Result<Self::Service>> + Send;
+
+    /// 实例所属的连接或流结束后调用，之后实例随最后一个请求结束而释放
+    fn on_disconnect(&self, _service: &Self::Service, _ctx: &Context) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+impl<F, T> ServiceFactory for F
+where
+    F: Fn(&Context) -> T + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+{
+    type Service = T;
+
+    fn on_connect(&self, ctx: &Context) -> impl Future<Output = anyhow::Result<T>> + Send {
+        futures::future::ready(Ok(self(ctx)))
+    }
+}
+
+/// 创建一个实例，返回绑定到该实例的服务与实例结束时运行的 `on_disconnect`
+pub(crate) type ConnectFn = dyn Fn(&Context) -> BoxFuture<'static, anyhow::Result<(Service, BoxFuture<'static, ()>)>> + Send + Sync;
+
+// --- ConnectionInstances ---
+
+/// 一个连接上按 [`ServiceScope::Connection`] 创建的实例
+#[derive(Default)]
+pub(crate) struct ConnectionInstances {
+    services: Mutex<HashMap<&'static str, Arc<OnceCell<Service>>>>,
+    disconnects: Mutex<Vec<BoxFuture<'static, ()>>>,
+}
+
+impl ConnectionInstances {
+    /// 取连接上已有的实例，没有时创建；同时请求的流只创建一次
+    pub(crate) async fn get_or_connect(&self, name: &'static str, connect: &ConnectFn, ctx: &Context) -> anyhow::Result<Service> {
+        let cell = self.services.lock().entry(name).or_default().clone();
+        let service = cell
+            .get_or_try_init(|| async {
+                let (service, disconnect) = connect(ctx).await?;
+                self.disconnects.lock().push(disconnect);
+                anyhow::Ok(service)
+            })
+            .await?;
+        Ok(service.clone())
+    }
+
+    /// 运行所有实例的 `on_disconnect`
+    pub(crate) async fn close(&self) {
+        self.services.lock().clear();
+        let disconnects = std::mem::take(&mut *self.disconnects.lock());
+        futures::future::join_all(disconnects).await;
+    }
+}
+
+impl std::fmt::Debug for ConnectionInstances {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionInstances")
+            .field("services", &self.services.lock().keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -0,0 +1,419 @@
+use bytes::{BufMut, BytesMut};
+use nitrogen_utils::{CodecConfig, Compression, FrameCodec};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{Error, ErrorKind, CONTEXT_FEATURE};
+
+/// 握手消息的魔数
+pub const MAGIC: [u8; 4] = *b"NTRG";
+/// 当前协议版本
+///
+/// 版本 1 的请求编码为 `[method_key, [args...]]`，错误编码为 `[kind, message]`；
+/// 更早的、不经握手的对端无法解码这种格式，必须与本端同时升级。线格式的不兼容变更都需要提升该版本。
+pub const PROTOCOL_VERSION: u16 = 1;
+/// 握手消息体的最大长度
+pub const MAX_HANDSHAKE_LENGTH: usize = 64 * 1024;
+/// 支持的消息编码
+pub const CODECS: &[&str] = &["msgpack"];
+/// 支持的可选特性
+pub const FEATURES: &[&str] = &[CONTEXT_FEATURE];
+
+// --- 握手消息 ---
+
+/// 客户端发出的握手请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    /// 请求的服务，按 `NAME` 标识
+    pub services: Vec<String>,
+    /// 愿意使用的消息编码，按优先级排序
+    pub codecs: Vec<String>,
+    /// 愿意使用的压缩算法，按优先级排序
+    pub compression: Vec<Compression>,
+    /// 客户端能接收的单帧最大长度
+    pub max_frame_length: u64,
+    /// 希望启用的可选特性
+    pub features: Vec<String>,
+}
+
+/// 服务端的握手回复
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Reply {
+    Accept(Accepted),
+    Reject(Rejected),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Accepted {
+    /// 接受的服务，是请求的服务的子集
+    pub services: Vec<String>,
+    pub codec: String,
+    pub compression: Compression,
+    /// 服务端能接收的单帧最大长度
+    pub max_frame_length: u64,
+    /// 双方都支持的可选特性
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rejected {
+    pub reason: RejectReason,
+    pub message: String,
+}
+
+/// 拒绝原因
+///
+/// 在线上以名称字符串传输，无法识别的原因解码为 [`RejectReason::Other`]。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "&'static str")]
+pub enum RejectReason {
+    UnsupportedVersion,
+    UnknownService,
+    UnsupportedCodec,
+    Other,
+}
+
+impl RejectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectReason::UnsupportedVersion => "UnsupportedVersion",
+            RejectReason::UnknownService => "UnknownService",
+            RejectReason::UnsupportedCodec => "UnsupportedCodec",
+            RejectReason::Other => "Other",
+        }
+    }
+}
+
+impl From<String> for RejectReason {
+    fn from(reason: String) -> Self {
+        match reason.as_str() {
+            "UnsupportedVersion" => RejectReason::UnsupportedVersion,
+            "UnknownService" => RejectReason::UnknownService,
+            "UnsupportedCodec" => RejectReason::UnsupportedCodec,
+            _ => RejectReason::Other,
+        }
+    }
+}
+
+impl From<RejectReason> for &'static str {
+    fn from(reason: RejectReason) -> Self {
+        reason.as_str()
+    }
+}
+
+impl From<Rejected> for Error {
+    fn from(rejected: Rejected) -> Self {
+        let kind = match rejected.reason {
+            RejectReason::UnknownService => ErrorKind::UnknownService,
+            _ => ErrorKind::Handshake,
+        };
+        Error::new(kind, format!("handshake rejected ({}): {}", rejected.reason.as_str(), rejected.message))
+    }
+}
+
+// --- 客户端 ---
+
+/// 客户端握手：请求 `services`，并按服务端接受的参数构造编解码器
+///
+/// 服务端拒绝时返回的错误带有拒绝原因；服务端只接受了部分服务时，接受的服务见返回的 [`Accepted`]。
+pub async fn client_handshake<I>(io: &mut I, services: &[&str], config: &CodecConfig) -> Result<(FrameCodec, Accepted), Error>
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    let name = services.join("|");
+    let hello = Hello {
+        services: services.iter().map(|service| service.to_string()).collect(),
+        codecs: CODECS.iter().map(|codec| codec.to_string()).collect(),
+        compression: config.compression.clone(),
+        max_frame_length: config.max_frame_length as u64,
+        features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+    };
+
+    let reply = tokio::time::timeout(config.handshake_timeout, async {
+        write_handshake(io, &hello).await?;
+        io.flush().await?;
+        match read_handshake::<_, Reply>(io).await? {
+            (_, Some(reply)) => Ok(reply),
+            (version, None) => anyhow::bail!("server protocol version {} is not supported", version),
+        }
+    })
+    .await
+    .map_err(|_| Error::new(ErrorKind::Timeout, format!("{} handshake timeout", name)))?
+    .map_err(|err| Error::new(ErrorKind::Handshake, format!("{} handshake error: {}", name, err)))?;
+
+    let accepted = match reply {
+        Reply::Accept(accepted) => accepted,
+        Reply::Reject(rejected) => return Err(rejected.into()),
+    };
+
+    let compression = accepted.compression;
+    if !compression.is_supported() || !(compression == Compression::None || config.compression.contains(&compression)) {
+        return Err(Error::new(
+            ErrorKind::Handshake,
+            format!("server selected unsupported compression: {:?}", compression),
+        ));
+    }
+    if !CODECS.contains(&accepted.codec.as_str()) {
+        return Err(Error::new(
+            ErrorKind::Handshake,
+            format!("server selected unsupported codec: {}", accepted.codec),
+        ));
+    }
+
+    let codec = FrameCodec::new(config, compression).with_peer_max_frame_length(accepted.max_frame_length as usize);
+    Ok((codec, accepted))
+}
+
+// --- 服务端 ---
+
+/// 服务端已收到、尚未回复的握手
+#[derive(Debug)]
+pub struct IncomingHandshake {
+    hello: Hello,
+    config: CodecConfig,
+}
+
+/// 服务端握手：读取客户端的 [`Hello`]
+///
+/// 魔数、长度不合法或超时返回错误；协议版本不受支持时回复拒绝后返回错误。
+pub async fn accept_handshake<I>(io: &mut I, config: &CodecConfig) -> anyhow::Result<IncomingHandshake>
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    let (version, hello) = tokio::time::timeout(config.handshake_timeout, read_handshake::<_, Hello>(io))
+        .await
+        .map_err(|_| anyhow::anyhow!("handshake timeout"))??;
+
+    let hello = match hello {
+        Some(hello) if version == PROTOCOL_VERSION => hello,
+        _ => {
+            let message = format!("protocol version {} is not supported, expected {}", version, PROTOCOL_VERSION);
+            reply(
+                io,
+                Reply::Reject(Rejected {
+                    reason: RejectReason::UnsupportedVersion,
+                    message: message.clone(),
+                }),
+            )
+            .await?;
+            anyhow::bail!(message);
+        }
+    };
+
+    Ok(IncomingHandshake { hello, config: config.clone() })
+}
+
+impl IncomingHandshake {
+    pub fn hello(&self) -> &Hello {
+        &self.hello
+    }
+
+    /// 以 `config` 代替读取握手时的配置来协商编解码参数，用于按服务区分配置
+    pub fn with_config(mut self, config: CodecConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 请求的服务，请求信封中的服务序号即在其中的位置
+    pub fn services(&self) -> &[String] {
+        &self.hello.services
+    }
+
+    /// 接受 `services`，回复协商出的参数并返回对应的编解码器
+    pub async fn accept<I>(self, io: &mut I, services: &[&str]) -> anyhow::Result<FrameCodec>
+    where
+        I: AsyncRead + AsyncWrite + Send + Unpin,
+    {
+        let codec = match self.hello.codecs.iter().find(|codec| CODECS.contains(&codec.as_str())) {
+            Some(codec) => codec.clone(),
+            None => {
+                let message = format!("none of the codecs {:?} is supported", self.hello.codecs);
+                self.reject(io, RejectReason::UnsupportedCodec, message.clone()).await?;
+                anyhow::bail!(message);
+            }
+        };
+
+        let accepted = Accepted {
+            services: services.iter().map(|service| service.to_string()).collect(),
+            codec,
+            compression: Compression::negotiate(&self.hello.compression, &self.config.compression),
+            max_frame_length: self.config.max_frame_length as u64,
+            features: self
+                .hello
+                .features
+                .iter()
+                .filter(|feature| FEATURES.contains(&feature.as_str()))
+                .cloned()
+                .collect(),
+        };
+        let compression = accepted.compression;
+
+        reply(io, Reply::Accept(accepted)).await?;
+
+        Ok(FrameCodec::new(&self.config, compression).with_peer_max_frame_length(self.hello.max_frame_length as usize))
+    }
+
+    /// 拒绝握手，并告知客户端原因
+    pub async fn reject<I>(self, io: &mut I, reason: RejectReason, message: impl Into<String>) -> anyhow::Result<()>
+    where
+        I: AsyncRead + AsyncWrite + Send + Unpin,
+    {
+        let message = message.into();
+        reply(io, Reply::Reject(Rejected { reason, message })).await
+    }
+}
+
+async fn reply<I>(io: &mut I, reply: Reply) -> anyhow::Result<()>
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    write_handshake(io, &reply).await?;
+    io.flush().await?;
+    Ok(())
+}
+
+// --- 编解码 ---
+
+/// ```text
+/// +----------+---------------+----------------+-------------------+
+/// | "NTRG"   | version: u16  | length: u32    | body: MessagePack |
+/// +----------+---------------+----------------+-------------------+
+/// ```
+async fn write_handshake<I, T>(io: &mut I, msg: &T) -> anyhow::Result<()>
+where
+    I: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let body = rmp_serde::to_vec(msg)?;
+    if body.len() > MAX_HANDSHAKE_LENGTH {
+        anyhow::bail!("handshake message too large: {} > {}", body.len(), MAX_HANDSHAKE_LENGTH);
+    }
+
+    let mut buf = BytesMut::with_capacity(MAGIC.len() + 2 + 4 + body.len());
+    buf.put_slice(&MAGIC);
+    buf.put_u16(PROTOCOL_VERSION);
+    buf.put_u32(body.len() as u32);
+    buf.put_slice(&body);
+    io.write_all(&buf).await?;
+    Ok(())
+}
+
+/// 读取一条握手消息，版本不一致时消息体按未知格式丢弃并返回 `None`
+async fn read_handshake<I, T>(io: &mut I) -> anyhow::Result<(u16, Option<T>)>
+where
+    I: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut magic = [0u8; 4];
+    io.read_exact(&mut magic).await?;
+    if magic != MAGIC {
+        anyhow::bail!("invalid handshake magic: {:?}", magic);
+    }
+
+    let version = io.read_u16().await?;
+    let length = io.read_u32().await? as usize;
+    if length > MAX_HANDSHAKE_LENGTH {
+        anyhow::bail!("handshake message too large: {} > {}", length, MAX_HANDSHAKE_LENGTH);
+    }
+
+    let mut body = vec![0u8; length];
+    io.read_exact(&mut body).await?;
+
+    if version != PROTOCOL_VERSION {
+        return Ok((version, None));
+    }
+
+    Ok((version, Some(rmp_serde::from_slice(&body)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{duplex, BufStream, DuplexStream};
+
+    use super::*;
+
+    fn config() -> CodecConfig {
+        CodecConfig {
+            handshake_timeout: Duration::from_secs(1),
+            ..CodecConfig::default()
+        }
+    }
+
+    /// 客户端经过带缓冲的流，未 flush 的握手会超时
+    fn pair() -> (BufStream<DuplexStream>, DuplexStream) {
+        let (client, server) = duplex(1024);
+        (BufStream::new(client), server)
+    }
+
+    #[tokio::test]
+    async fn accepts_requested_services() {
+        let (mut client, mut server) = pair();
+        let server = tokio::spawn(async move {
+            let handshake = accept_handshake(&mut server, &config()).await.unwrap();
+            assert_eq!(handshake.services(), ["A", "B"]);
+            handshake.accept(&mut server, &["A"]).await.unwrap();
+        });
+
+        let (_, accepted) = client_handshake(&mut client, &["A", "B"], &config()).await.unwrap();
+        assert_eq!(accepted.services, ["A"]);
+        assert_eq!(accepted.codec, "msgpack");
+        assert_eq!(accepted.features, FEATURES);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_reject_reason() {
+        let (mut client, mut server) = pair();
+        tokio::spawn(async move {
+            let handshake = accept_handshake(&mut server, &config()).await.unwrap();
+            handshake.reject(&mut server, RejectReason::UnknownService, "no service B").await.unwrap();
+        });
+
+        let err = client_handshake(&mut client, &["B"], &config()).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnknownService);
+        assert!(err.message.contains("UnknownService") && err.message.contains("no service B"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_protocol_versions() {
+        let (mut client, mut server) = duplex(1024);
+        let server = tokio::spawn(async move { accept_handshake(&mut server, &config()).await.map(|_| ()) });
+
+        client.write_all(&MAGIC).await.unwrap();
+        client.write_u16(PROTOCOL_VERSION + 1).await.unwrap();
+        client.write_u32(3).await.unwrap();
+        client.write_all(b"???").await.unwrap();
+
+        match read_handshake::<_, Reply>(&mut client).await.unwrap() {
+            (PROTOCOL_VERSION, Some(Reply::Reject(rejected))) => assert_eq!(rejected.reason, RejectReason::UnsupportedVersion),
+            reply => panic!("unexpected reply: {:?}", reply),
+        }
+        assert!(server.await.unwrap().is_err());
+
+        // 客户端同样拒绝版本不同的回复
+        let (mut client, mut server) = pair();
+        tokio::spawn(async move {
+            read_handshake::<_, Hello>(&mut server).await.unwrap();
+            server.write_all(&MAGIC).await.unwrap();
+            server.write_u16(PROTOCOL_VERSION + 1).await.unwrap();
+            server.write_u32(0).await.unwrap();
+        });
+        let err = client_handshake(&mut client, &["A"], &config()).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Handshake);
+        assert!(err.message.contains("protocol version"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_length() {
+        let (mut client, mut server) = duplex(1024);
+        client.write_all(&MAGIC).await.unwrap();
+        client.write_u16(PROTOCOL_VERSION).await.unwrap();
+        client.write_u32(MAX_HANDSHAKE_LENGTH as u32 + 1).await.unwrap();
+
+        // 不等待消息体，立即失败
+        let err = accept_handshake(&mut server, &config()).await.unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+    }
+}
@@ -1,7 +1,29 @@
-mod negotiator;
+extern crate self as nitrogen;
+
+mod access;
+mod context;
+mod dynamic;
+mod factory;
+mod handshake;
+mod mock;
+mod reflection;
+mod router;
 mod rpc_service;
+mod schema;
+mod session;
 
+pub use async_trait::async_trait;
 pub use nitrogen_macro::*;
 pub use nitrogen_utils::*;
+pub use rmpv;
+
+pub use {access::*, context::*, dynamic::*, factory::*, handshake::*, mock::*, reflection::*, router::*, rpc_service::*, schema::*, session::*};
 
-pub use {negotiator::*, rpc_service::*};
+/// rpc_service 生成的代码通过这里引用依赖，使用方只需要依赖 nitrogen
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use serde;
+    pub use tokio;
+    pub use tracing;
+}
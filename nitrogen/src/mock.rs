@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::{Error, ErrorKind, Result};
+
+// 由 `#[rpc_service(mock)]` 生成的 `Mock*` 为每个方法保存一个 MockMethod：
+//
+// let mock = MockMyService::new();
+// mock.expect_hello().returns(|(name,)| Ok(format!("hello, {}", name)));
+// let client = mock.client();
+// assert_eq!(client.hello("a".into()).await?, "hello, a");
+// mock.expect_hello().assert_called(1);
+
+type StubFn<Args, Ret> = dyn Fn(Args) -> Result<Ret> + Send + Sync;
+
+/// 单个方法的桩与调用记录，克隆后共享同一份状态
+///
+/// `Args` 为参数组成的元组，引用参数记录为拥有所有权的形式。
+pub struct MockMethod<Args, Ret> {
+    name: &'static str,
+    state: Arc<Mutex<MockState<Args, Ret>>>,
+}
+
+struct MockState<Args, Ret> {
+    stub: Option<Arc<StubFn<Args, Ret>>>,
+    calls: Vec<Args>,
+}
+
+impl<Args, Ret> MockMethod<Args, Ret>
+where
+    Args: Clone + 'static,
+    Ret: 'static,
+{
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            state: Arc::new(Mutex::new(MockState { stub: None, calls: vec![] })),
+        }
+    }
+
+    /// 每次调用由 `stub` 计算返回值
+    ///
+    /// 通过 `Mock*::client` 调用时，`Err` 原样返回给客户端，可以模拟超时等传输层错误。
+    pub fn returns<F>(&self, stub: F) -> &Self
+    where
+        F: Fn(Args) -> Result<Ret> + Send + Sync + 'static,
+    {
+        self.state.lock().stub = Some(Arc::new(stub));
+        self
+    }
+
+    /// 每次调用都返回 `value`
+    pub fn returns_value(&self, value: Ret) -> &Self
+    where
+        Ret: Clone + Send + Sync,
+    {
+        self.returns(move |_| Ok(value.clone()))
+    }
+
+    /// 每次调用都返回 `err`
+    pub fn returns_error(&self, err: Error) -> &Self {
+        self.returns(move |_| Err(err.clone()))
+    }
+
+    /// 按调用顺序记录的参数
+    pub fn calls(&self) -> Vec<Args> {
+        self.state.lock().calls.clone()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.state.lock().calls.len()
+    }
+
+    /// 调用次数不是 `times` 时 panic
+    pub fn assert_called(&self, times: usize) {
+        let count = self.call_count();
+        assert_eq!(count, times, "{} was called {} time(s), expected {}", self.name, count, times);
+    }
+
+    /// 清除桩与调用记录
+    pub fn reset(&self) {
+        let mut state = self.state.lock();
+        state.stub = None;
+        state.calls.clear();
+    }
+
+    /// 记录参数并调用桩；未设置桩时返回 [`ErrorKind::Other`]
+    pub fn call(&self, args: Args) -> Result<Ret> {
+        let stub = {
+            let mut state = self.state.lock();
+            state.calls.push(args.clone());
+            state.stub.clone()
+        };
+
+        match stub {
+            Some(stub) => stub(args),
+            None => Err(Error::new(ErrorKind::Other, format!("{} is not stubbed", self.name))),
+        }
+    }
+}
+
+impl<Args, Ret> Clone for MockMethod<Args, Ret> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<Args, Ret> std::fmt::Debug for MockMethod<Args, Ret> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockMethod")
+            .field("name", &self.name)
+            .field("calls", &self.state.lock().calls.len())
+            .finish()
+    }
+}
@@ -0,0 +1,60 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use nitrogen_macro::rpc_service;
+use nitrogen_utils::Compression;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{ServiceSchema, CODECS, FEATURES, PROTOCOL_VERSION};
+
+/// 服务端的协议信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub protocol_version: u16,
+    pub codecs: Vec<String>,
+    pub compression: Vec<Compression>,
+    pub features: Vec<String>,
+    /// 已注册的服务名
+    pub services: Vec<String>,
+}
+
+/// 内置的反射服务，通过 [`crate::Router::add_reflection`] 注册
+#[rpc_service]
+pub trait Reflection {
+    /// 协议版本、编码、压缩算法与已注册的服务
+    async fn server_info(&self) -> ServerInfo;
+    /// 所有已注册服务的描述
+    async fn list_services(&self) -> Vec<ServiceSchema>;
+    /// 按服务名查找描述
+    async fn describe_service(&self, name: String) -> Option<ServiceSchema>;
+}
+
+/// 与 [`crate::Router`] 共享的服务描述
+pub(crate) type SchemaRegistry = Arc<RwLock<BTreeMap<&'static str, ServiceSchema>>>;
+
+#[derive(Clone)]
+pub(crate) struct ReflectionImpl {
+    pub(crate) schemas: SchemaRegistry,
+    pub(crate) compression: Vec<Compression>,
+}
+
+#[async_trait::async_trait]
+impl Reflection for ReflectionImpl {
+    async fn server_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: PROTOCOL_VERSION,
+            codecs: CODECS.iter().map(|codec| codec.to_string()).collect(),
+            compression: self.compression.clone(),
+            features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+            services: self.schemas.read().keys().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    async fn list_services(&self) -> Vec<ServiceSchema> {
+        self.schemas.read().values().cloned().collect()
+    }
+
+    async fn describe_service(&self, name: String) -> Option<ServiceSchema> {
+        self.schemas.read().get(name.as_str()).cloned()
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use nitrogen_utils::{BiConnnectionAcceptor, BiConnnectionSplit, BiListener, BiStream, BoxedBiStream, CodecConfig};
+use tokio::task::JoinSet;
+
+use crate::{
+    accept_handshake, reflection::SchemaRegistry, serve_services_with_context, AccessPolicy, ConnectionInfo, Context, ReflectionExt, ReflectionImpl, Service,
+    ServiceOptions,
+};
+
+/// 接受连接出错后，再次接受前等待的时间
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+// --- Router ---
+
+/// 按握手中请求的服务名分发流
+///
+/// ```ignore
+/// let mut router = nitrogen::Router::new();
+/// router.add(MyServiceImpl.into_service());
+/// router.run(QuicListener::bind(addr).await?).await?;
+/// ```
+#[derive(Debug, Default)]
+pub struct Router {
+    config: CodecConfig,
+    services: HashMap<&'static str, (Service, ServiceOptions)>,
+    schemas: SchemaRegistry,
+    access_policy: Option<Arc<AccessPolicy>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使用 `config` 进行握手，并作为各服务默认的编解码配置
+    pub fn with_config(config: CodecConfig) -> Self {
+        Self { config, ..Default::default() }
+    }
+
+    /// 注册服务，同名服务会被替换
+    pub fn add(&mut self, service: Service) -> &mut Self {
+        self.add_with_options(service, ServiceOptions::default())
+    }
+
+    pub fn add_with_options(&mut self, service: Service, options: ServiceOptions) -> &mut Self {
+        match service.schema() {
+            Some(schema) => self.schemas.write().insert(service.name(), schema.clone()),
+            None => self.schemas.write().remove(service.name()),
+        };
+        self.services.insert(service.name(), (service, options));
+        self
+    }
+
+    /// 按调用方的证书身份检查所有服务的请求，服务自己的 [`ServiceOptions::access_policy`] 优先
+    ///
+    /// 对端身份来自 [`Router::serve_connection`] 的连接，直接交给 [`Router::serve_stream`] 的流没有身份。
+    pub fn set_access_policy(&mut self, policy: AccessPolicy) -> &mut Self {
+        self.access_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// 注册内置的 [`crate::Reflection`] 服务，列出所有已注册（包括之后注册）的服务及其方法
+    pub fn add_reflection(&mut self) -> &mut Self {
+        let reflection = ReflectionImpl {
+            schemas: self.schemas.clone(),
+            compression: self.config.compression.clone(),
+        };
+        self.add(reflection.into_service())
+    }
+
+    /// 已注册的服务名
+    pub fn services(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.services.keys().copied()
+    }
+
+    /// 已注册的服务及其选项，服务没有设置访问策略时带上 [`Router::set_access_policy`] 的策略
+    pub fn lookup(&self, name: &str) -> Option<(Service, ServiceOptions)> {
+        let (service, mut options) = self.services.get(name).cloned()?;
+        if options.access_policy.is_none() {
+            options.access_policy = self.access_policy.clone();
+        }
+        Some((service, options))
+    }
+
+    /// 在一条流上完成握手，并交给请求的服务处理
+    ///
+    /// 一条流可以同时请求多个已注册的服务；请求的服务都未注册时回复 [`crate::RejectReason::UnknownService`]。
+    pub async fn serve_stream<S>(&self, stream: S)
+    where
+        S: BiStream + 'static,
+    {
+        let ctx = Context::detached();
+        self.serve_stream_with_context(stream, ctx.clone()).await;
+        ctx.connection().close().await;
+    }
+
+    /// 同 [`Router::serve_stream`]，流上的请求从 `ctx` 派生出各自的 [`Context`]
+    ///
+    /// 按连接创建的服务实例保存在 `ctx` 的连接信息中，连接结束后需要调用 [`ConnectionInfo::close`]。
+    pub async fn serve_stream_with_context<S>(&self, stream: S, ctx: Context)
+    where
+        S: BiStream + 'static,
+    {
+        let mut stream: BoxedBiStream = Box::pin(stream);
+
+        let handshake = match accept_handshake(&mut stream, &self.config).await {
+            Ok(handshake) => handshake,
+            Err(err) => return tracing::error!("Router::serve handshake error: {}", err),
+        };
+
+        tracing::debug!("Router::serve {:?}", handshake.services());
+        let lookup = |name: &str| self.lookup(name);
+        if let Err(err) = serve_services_with_context(stream, handshake, lookup, ctx).await {
+            tracing::warn!("Router::serve error: {}", err);
+        }
+    }
+
+    /// 接受一个连接上的所有流，每条流在独立的任务中处理
+    ///
+    /// 请求的 [`Context`] 中带有连接的对端信息与流的序号。
+    /// 连接关闭且所有流结束后，运行按连接创建的服务实例的 `on_disconnect`。
+    pub async fn serve_connection<C>(self: Arc<Self>, connection: C)
+    where
+        C: BiConnnectionSplit,
+        C::Opener: Send,
+        C::Acceptor: Send,
+        <C::Acceptor as BiConnnectionAcceptor>::Stream: Send + 'static,
+    {
+        let connection_info = Arc::new(ConnectionInfo::new(connection.peer_info()));
+        let (_opener, mut acceptor) = connection.split();
+        let mut streams = JoinSet::new();
+
+        for stream_id in 0.. {
+            match acceptor.accept().await {
+                Ok(stream) => {
+                    let router = self.clone();
+                    let ctx = Context::for_stream(connection_info.clone(), stream_id);
+                    streams.spawn(async move { router.serve_stream_with_context(stream, ctx).await });
+                    while streams.try_join_next().is_some() {}
+                }
+                Err(err) => {
+                    tracing::debug!("Router::serve connection closed: {}", err);
+                    break;
+                }
+            }
+        }
+
+        while streams.join_next().await.is_some() {}
+        connection_info.close().await;
+    }
+
+    /// 接受 `listener` 上的所有连接，直到监听关闭
+    ///
+    /// 单个连接建立失败只记录日志，不影响之后的连接。
+    pub async fn run<L>(self, mut listener: L) -> anyhow::Result<()>
+    where
+        L: BiListener,
+        L::Connection: Send + 'static,
+        <L::Connection as BiConnnectionSplit>::Opener: Send,
+        <L::Connection as BiConnnectionSplit>::Acceptor: Send,
+        <<L::Connection as BiConnnectionSplit>::Acceptor as BiConnnectionAcceptor>::Stream: Send + 'static,
+    {
+        let router = Arc::new(self);
+
+        loop {
+            match listener.accept().await {
+                Ok(Some(connection)) => {
+                    tokio::spawn(router.clone().serve_connection(connection));
+                }
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    tracing::warn!("Router::run accept error: {}", err);
+                    // 避免持续出错（如文件描述符耗尽）时空转
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                }
+            }
+        }
+    }
+}
@@ -1,108 +1,828 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, future::Future, sync::Arc};
 
 use futures::{
-    channel::{mpsc, oneshot},
-    SinkExt, StreamExt,
+    future::{BoxFuture, Either},
+    FutureExt, SinkExt, StreamExt,
+};
+use nitrogen_utils::{channel_sender_with_sink, decode_message, encode_message, framed_tokio_io, Frame, FramedTokioIO};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::Semaphore,
+};
+
+use crate::{
+    factory::ConnectFn, AccessPolicy, Context, IncomingHandshake, Metadata, RejectReason, ServiceFactory, ServiceSchema, ServiceScope, Session, CONTEXT_FEATURE,
 };
-use nitrogen_utils::{channel_sender_with_sink, framed_message_pack};
-use serde::{Deserialize, Serialize};
-use tokio_util::codec::LengthDelimitedCodec;
 
 // --- Message ---
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 线上的消息信封，编码为 `[id, payload, service, timeout, metadata]`
+///
+/// 末尾为默认值的字段在线上省略；超时与元数据只在握手协商了 [`CONTEXT_FEATURE`] 时发送，
+/// 不认识这两个字段的旧服务端不会收到它们。
+#[derive(Debug, Clone, Deserialize)]
 pub struct Message<T> {
     pub id: u64,
     pub payload: T,
+    /// 请求所属的服务，即该服务在握手请求中的序号
+    #[serde(default)]
+    pub service: u32,
+    /// 客户端等待响应的时间，毫秒
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+impl<T> Message<T> {
+    pub fn new(id: u64, payload: T) -> Self {
+        Self {
+            id,
+            payload,
+            service: 0,
+            timeout: None,
+            metadata: Metadata::new(),
+        }
+    }
+
+    pub fn with_service(mut self, service: u32) -> Self {
+        self.service = service;
+        self
+    }
+}
+
+impl<T> Serialize for Message<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let len = if !self.metadata.is_empty() {
+            5
+        } else if self.timeout.is_some() {
+            4
+        } else if self.service != 0 {
+            3
+        } else {
+            2
+        };
+
+        let mut state = serializer.serialize_struct("Message", len)?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("payload", &self.payload)?;
+        if len > 2 {
+            state.serialize_field("service", &self.service)?;
+        }
+        if len > 3 {
+            state.serialize_field("timeout", &self.timeout)?;
+        }
+        if len > 4 {
+            state.serialize_field("metadata", &self.metadata)?;
+        }
+        state.end()
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Error(pub String);
+// --- Error ---
+
+/// 错误类别
+///
+/// 在线上以名称字符串传输，无法识别的类别（来自更新版本的对端）解码为 [`ErrorKind::Other`]。
+/// 之后的版本可能增加类别，匹配时需要保留通配分支。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "&'static str")]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// 发送或接收失败
+    Transport,
+    /// 握手失败或被服务端拒绝
+    Handshake,
+    /// 服务端不提供请求的服务
+    UnknownService,
+    /// 请求超时
+    Timeout,
+    /// 服务端不认识该方法
+    UnknownMethod,
+    /// 请求无法解码，例如参数类型不匹配
+    InvalidRequest,
+    /// 响应无法解码或与请求不匹配
+    InvalidResponse,
+    /// 调用方无权调用该方法，见 [`AccessPolicy`]
+    PermissionDenied,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Transport => "Transport",
+            ErrorKind::Handshake => "Handshake",
+            ErrorKind::UnknownService => "UnknownService",
+            ErrorKind::Timeout => "Timeout",
+            ErrorKind::UnknownMethod => "UnknownMethod",
+            ErrorKind::InvalidRequest => "InvalidRequest",
+            ErrorKind::InvalidResponse => "InvalidResponse",
+            ErrorKind::PermissionDenied => "PermissionDenied",
+            ErrorKind::Other => "Other",
+        }
+    }
+}
+
+impl From<String> for ErrorKind {
+    fn from(kind: String) -> Self {
+        match kind.as_str() {
+            "Transport" => ErrorKind::Transport,
+            "Handshake" => ErrorKind::Handshake,
+            "UnknownService" => ErrorKind::UnknownService,
+            "Timeout" => ErrorKind::Timeout,
+            "UnknownMethod" => ErrorKind::UnknownMethod,
+            "InvalidRequest" => ErrorKind::InvalidRequest,
+            "InvalidResponse" => ErrorKind::InvalidResponse,
+            "PermissionDenied" => ErrorKind::PermissionDenied,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<ErrorKind> for &'static str {
+    fn from(kind: ErrorKind) -> Self {
+        kind.as_str()
+    }
+}
+
+/// RPC 错误
+///
+/// 在线上编码为 `[kind, message]`。协议版本 1 之前的对端把错误编码为单个字符串，两者互不兼容，见 [`PROTOCOL_VERSION`]。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
 
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Nitrogen Error: {}", self.0)
+        write!(f, "Nitrogen Error: {}", self.message)
     }
 }
 
-// RpcServiceClient 通过 rpc_service 自动实现
+// --- MethodKey ---
+
+/// 方法在线上的标识
+///
+/// 默认为方法名，`#[rpc(id = N)]` 指定后为数字 id；服务端对两者都能识别。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MethodKey {
+    Id(u32),
+    Name(Cow<'static, str>),
+}
+
+impl MethodKey {
+    pub const fn name(name: &'static str) -> Self {
+        MethodKey::Name(Cow::Borrowed(name))
+    }
+
+    /// 是否指向 id 为 `id`（若有）、名称为 `name` 的方法
+    pub fn matches(&self, id: Option<u32>, name: &str) -> bool {
+        match self {
+            MethodKey::Id(key) => Some(*key) == id,
+            MethodKey::Name(key) => key == name,
+        }
+    }
+}
+
+impl std::fmt::Display for MethodKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MethodKey::Id(id) => write!(f, "#{}", id),
+            MethodKey::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// RpcRequest 与 RpcResponse 通过 rpc_service 自动实现
 
-#[async_trait::async_trait]
-pub trait RpcServiceClient<Req, Resp>
+/// 客户端收到响应时按请求的方法解码，因此响应在线上不携带方法标识
+pub type ResponseDecoder<Resp> = fn(&Frame) -> std::io::Result<Message<Resp>>;
+
+pub trait RpcRequest<Resp> {
+    /// 该请求对应的响应解码方式
+    fn response_decoder(&self) -> ResponseDecoder<Resp>;
+}
+
+pub trait RpcResponse {
+    /// 不属于任何方法的错误响应，例如未知方法或无法解码的请求
+    fn from_error(err: Error) -> Self;
+}
+
+/// 以 `Result<T>` 解码响应，并通过 `variant` 包装为响应枚举
+#[doc(hidden)]
+pub fn decode_response<T, Resp>(frame: &Frame, variant: fn(Result<T>) -> Resp) -> std::io::Result<Message<Resp>>
 where
-    Req: serde::Serialize + Send + 'static,
-    Resp: serde::de::DeserializeOwned + Send + 'static,
+    T: serde::de::DeserializeOwned,
 {
-    const NAME: &'static str;
+    let Message { id, payload, .. } = decode_message::<Message<Result<T>>>(frame)?;
+    Ok(Message::new(id, variant(payload)))
+}
 
-    fn tx(&self) -> mpsc::Sender<(Req, oneshot::Sender<Resp>)>;
+/// 解码一个只可能是错误的响应
+#[doc(hidden)]
+pub fn decode_error_response<Resp>(frame: &Frame) -> std::io::Result<Message<Resp>>
+where
+    Resp: RpcResponse,
+{
+    let Message { id, payload, .. } = decode_message::<Message<Result<IgnoredAny>>>(frame)?;
+    let err = payload.err().unwrap_or_else(|| Error::new(ErrorKind::InvalidResponse, "unexpected response"));
+    Ok(Message::new(id, Resp::from_error(err)))
+}
 
-    #[doc(hidden)]
-    fn spawn<S>(self, mut rx: mpsc::Receiver<(Req, oneshot::Sender<Resp>)>, stream: S) -> Self
+/// 请求中多出本端不认识的末尾参数，通常来自新增了参数的新版本客户端
+///
+/// 这些参数被忽略；它们的值不一定是新版本中的默认值，因此记录警告。
+#[doc(hidden)]
+pub fn ignore_trailing_args(service: &str, method: &str, count: usize) {
+    if count > 0 {
+        tracing::warn!("{}::{} ignored {} trailing arguments unknown to this version", service, method, count);
+    }
+}
+
+// --- 服务端 ---
+
+/// 单个服务的选项
+#[derive(Debug, Clone, Default)]
+pub struct ServiceOptions {
+    /// 该服务使用的编解码配置，为空时使用握手时的配置
+    pub codec: Option<nitrogen_utils::CodecConfig>,
+    /// 每条流上同时处理的最大请求数，为空时不限制
+    pub max_concurrent_requests: Option<usize>,
+    /// 按调用方身份检查每个请求，为空时不检查
+    pub access_policy: Option<Arc<AccessPolicy>>,
+}
+
+type HandleFn = dyn Fn(&Context, u64, &Frame) -> BoxFuture<'static, Option<Frame>> + Send + Sync;
+
+/// 擦除了请求/响应类型的服务，由 `*Ext::into_service` 或 `*Ext::from_factory` 构造
+#[derive(Clone)]
+pub struct Service {
+    name: &'static str,
+    schema: Option<ServiceSchema>,
+    kind: ServiceKind,
+}
+
+#[derive(Clone)]
+enum ServiceKind {
+    /// 所有连接共享同一个实例
+    Shared(Arc<HandleFn>),
+    /// 处理请求前先由工厂创建实例
+    Factory(Arc<ConnectFn>, ServiceScope),
+}
+
+impl Service {
+    /// 每个请求由 `route` 处理；无法解码的请求回复 [`ErrorKind::InvalidRequest`]
+    ///
+    /// `route` 返回的 future 在请求的 [`Context`] 中运行，可以通过 [`Context::current`] 获取；
+    /// 为擦除类型，每个请求的 future 被 box 一次。
+    pub fn new<Req, Resp, F, Fut>(name: &'static str, route: F) -> Self
     where
-        Self: Sized,
-        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+        Req: serde::de::DeserializeOwned + Send + 'static,
+        Resp: serde::Serialize + RpcResponse + Send + 'static,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Resp> + Send + 'static,
     {
-        tokio::spawn(async move {
-            let framed_io = LengthDelimitedCodec::builder().max_frame_length(1024 * 1024 * 16).new_framed(stream);
-
-            let (sender, mut receiver) = framed_message_pack::<Message<Resp>, Message<Req>, _>(framed_io).split();
-            let mut sender = channel_sender_with_sink(sender);
-
-            let mut cursor = 0;
-            let mut notifies = HashMap::new();
-
-            loop {
-                tokio::select! {
-                    write = rx.next() => {
-                        if let Some((payload, notify)) = write {
-                            cursor += 1;
-                            let id = cursor;
-                            if let Err(err) = sender.send(Message { id, payload }).await {
-                                tracing::error!("{}Client::request send error: {}", Self::NAME, err);
-                            } else {
-                                notifies.insert(id, notify);
-                            }
-                        } else {
-                            break;
-                        }
-                    }
-                    Some(result) = receiver.next() => {
-                        match result {
-                            Ok(Message { id, payload }) => {
-                                if let Some(notify) = notifies.remove(&id) {
-                                    let _ = notify.send(payload);
-                                }
-                            }
-                            Err(err) => {
-                                tracing::error!("{}Client::request recv error: {}", Self::NAME, err);
-                            }
-                        }
-                    }
+        let handle = move |ctx: &Context, id: u64, frame: &Frame| match decode_message::<Message<Req>>(frame) {
+            Ok(Message {
+                payload, timeout, metadata, ..
+            }) => {
+                let ctx = ctx.for_request(id, metadata, timeout.map(std::time::Duration::from_millis));
+                let response = ctx.run(route(payload));
+                async move { encode_response(name, id, response.await) }.boxed()
+            }
+            Err(err) => {
+                let payload = Resp::from_error(Error::new(ErrorKind::InvalidRequest, format!("{}::serve decode error: {}", name, err)));
+                futures::future::ready(encode_response(name, id, payload)).boxed()
+            }
+        };
+
+        Self {
+            name,
+            schema: None,
+            kind: ServiceKind::Shared(Arc::new(handle)),
+        }
+    }
+
+    /// 客户端请求该服务时由 `factory` 创建实例，请求由 `route` 交给所属连接或流的实例处理
+    ///
+    /// 实例默认按连接创建，见 [`Service::with_scope`]。
+    pub fn from_factory<Req, Resp, F, R, Fut>(name: &'static str, factory: F, route: R) -> Self
+    where
+        Req: serde::de::DeserializeOwned + Send + 'static,
+        Resp: serde::Serialize + RpcResponse + Send + 'static,
+        F: ServiceFactory,
+        R: Fn(Arc<F::Service>, Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Resp> + Send + 'static,
+    {
+        let factory = Arc::new(factory);
+        let route = Arc::new(route);
+        let connect = move |ctx: &Context| {
+            let (factory, route, ctx) = (factory.clone(), route.clone(), ctx.clone());
+            async move {
+                let instance = Arc::new(factory.on_connect(&ctx).await?);
+                let service = {
+                    let instance = instance.clone();
+                    Service::new(name, move |req| route(instance.clone(), req))
+                };
+                let disconnect = async move { factory.on_disconnect(&instance, &ctx).await }.boxed();
+                Ok((service, disconnect))
+            }
+            .boxed()
+        };
+
+        Self {
+            name,
+            schema: None,
+            kind: ServiceKind::Factory(Arc::new(connect), ServiceScope::default()),
+        }
+    }
+
+    /// 工厂创建的实例按连接还是按流划分，对共享实例的服务没有作用
+    pub fn with_scope(mut self, scope: ServiceScope) -> Self {
+        if let ServiceKind::Factory(_, current) = &mut self.kind {
+            *current = scope;
+        }
+        self
+    }
+
+    pub fn with_schema(mut self, schema: ServiceSchema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn schema(&self) -> Option<&ServiceSchema> {
+        self.schema.as_ref()
+    }
+
+    /// `key` 指向的方法的线上名称，按数字 id 请求时通过服务描述查找
+    ///
+    /// 服务描述中没有该方法时为空；没有描述的服务无法判断，返回标识本身。
+    pub(crate) fn resolve_method(&self, key: &MethodKey) -> Option<String> {
+        match &self.schema {
+            Some(schema) => schema
+                .methods
+                .iter()
+                .find(|method| key.matches(method.id, &method.name))
+                .map(|method| method.name.to_string()),
+            None => Some(key.to_string()),
+        }
+    }
+
+    /// 取 `ctx` 所属连接或流的实例，返回绑定到该实例的服务，以及按流创建时流结束后要运行的 `on_disconnect`
+    pub(crate) async fn bind(&self, ctx: &Context) -> anyhow::Result<(Service, Option<BoxFuture<'static, ()>>)> {
+        let (service, disconnect) = match &self.kind {
+            ServiceKind::Shared(_) => return Ok((self.clone(), None)),
+            ServiceKind::Factory(connect, ServiceScope::Stream) => {
+                let (service, disconnect) = connect(ctx).await?;
+                (service, Some(disconnect))
+            }
+            ServiceKind::Factory(connect, ServiceScope::Connection) => {
+                (ctx.connection().instances.get_or_connect(self.name, connect.as_ref(), ctx).await?, None)
+            }
+        };
+        Ok((service.with_schema_option(self.schema.clone()), disconnect))
+    }
+
+    fn with_schema_option(mut self, schema: Option<ServiceSchema>) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// 处理流上的一个已编码的请求，返回已编码的响应；工厂服务需要先 [`Service::bind`]
+    pub(crate) fn call(&self, ctx: &Context, id: u64, frame: &Frame) -> BoxFuture<'static, Option<Frame>> {
+        match &self.kind {
+            ServiceKind::Shared(handle) => handle(ctx, id, frame),
+            ServiceKind::Factory(..) => {
+                let payload = Result::<()>::Err(Error::new(ErrorKind::Other, format!("{}::serve service instance is not created", self.name)));
+                futures::future::ready(encode_message(&Message::new(id, payload)).ok()).boxed()
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Service").field("name", &self.name).finish()
+    }
+}
+
+fn encode_response<Resp>(name: &'static str, id: u64, payload: Resp) -> Option<Frame>
+where
+    Resp: serde::Serialize + RpcResponse,
+{
+    match encode_message(&Message::new(id, payload)) {
+        Ok(frame) => Some(frame),
+        Err(err) => {
+            let payload = Resp::from_error(Error::new(ErrorKind::InvalidResponse, format!("{}::serve encode error: {}", name, err)));
+            encode_message(&Message::new(id, payload))
+                .map_err(|err| tracing::error!("{}::serve encode error: {}", name, err))
+                .ok()
+        }
+    }
+}
+
+/// 回复握手，并在一条流上处理客户端请求的所有服务
+///
+/// `lookup` 按名称查找服务，请求的服务都不存在时回复 [`RejectReason::UnknownService`]；
+/// 多个服务时使用第一个设置了编解码配置的服务的配置。
+/// 工厂服务在回复握手前创建实例，创建失败时拒绝握手。
+/// 请求按信封中的服务序号分发，每个请求在独立的任务中处理，
+/// 无法解码的请求只要能读出 id 就回复错误，不会中断整条流。
+/// 服务描述中没有的方法回复 [`ErrorKind::UnknownMethod`]；
+/// 设置了 [`ServiceOptions::access_policy`] 的服务再按 `ctx` 中的对端身份检查每个请求，
+/// 被拒绝的请求回复 [`ErrorKind::PermissionDenied`] 并记录调用方。
+pub async fn serve_services<S, F>(stream: S, handshake: IncomingHandshake, lookup: F) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    F: Fn(&str) -> Option<(Service, ServiceOptions)> + Send,
+{
+    let ctx = Context::detached();
+    let result = serve_services_with_context(stream, handshake, lookup, ctx.clone()).await;
+    ctx.connection().close().await;
+    result
+}
+
+/// 同 [`serve_services`]，流上的请求从 `ctx` 派生出各自的 [`Context`]，流关闭时取消
+///
+/// 按连接创建的实例保存在 `ctx` 的连接信息中，连接结束后需要调用 [`crate::ConnectionInfo::close`]。
+pub async fn serve_services_with_context<S, F>(mut stream: S, handshake: IncomingHandshake, lookup: F, ctx: Context) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    F: Fn(&str) -> Option<(Service, ServiceOptions)> + Send,
+{
+    let services = handshake.hello().services.iter().map(|name| lookup(name)).collect::<Vec<_>>();
+    let accepted = services.iter().flatten().map(|(service, _)| service.name()).collect::<Vec<_>>();
+
+    if accepted.is_empty() {
+        let message = format!("unknown services: {:?}", handshake.hello().services);
+        handshake.reject(&mut stream, RejectReason::UnknownService, message.clone()).await?;
+        anyhow::bail!(message);
+    }
+
+    let handshake = match services.iter().flatten().find_map(|(_, options)| options.codec.clone()) {
+        Some(config) => handshake.with_config(config),
+        None => handshake,
+    };
+
+    let mut bound = Vec::with_capacity(services.len());
+    let mut disconnects = Vec::new();
+    for service in services {
+        let Some((service, options)) = service else {
+            bound.push(None);
+            continue;
+        };
+        match service.bind(&ctx).await {
+            Ok((service, disconnect)) => {
+                bound.push(Some((service, options)));
+                disconnects.extend(disconnect);
+            }
+            Err(err) => {
+                futures::future::join_all(disconnects).await;
+                let message = format!("{} on_connect error: {}", service.name(), err);
+                handshake.reject(&mut stream, RejectReason::Other, message.clone()).await?;
+                anyhow::bail!(message);
+            }
+        }
+    }
+
+    let result = match handshake.accept(&mut stream, &accepted).await {
+        Ok(codec) => {
+            serve_framed(&accepted.join("|"), framed_tokio_io(stream, codec), bound, &ctx).await;
+            Ok(())
+        }
+        Err(err) => Err(err),
+    };
+    ctx.cancellation_token().cancel();
+    futures::future::join_all(disconnects).await;
+    result
+}
+
+async fn serve_framed<S>(name: &str, framed_io: FramedTokioIO<S>, services: Vec<Option<(Service, ServiceOptions)>>, ctx: &Context)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let routes = services
+        .into_iter()
+        .map(|service| {
+            service.map(|(service, options)| {
+                let limit = options.max_concurrent_requests.map(|permits| Arc::new(Semaphore::new(permits)));
+                (service, limit, options.access_policy)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let (sender, mut receiver) = framed_io.split();
+    let sender = channel_sender_with_sink(sender);
+
+    while let Some(result) = receiver.next().await {
+        let frame = match result {
+            Ok(frame) => frame,
+            Err(err) => {
+                tracing::error!("{}::serve recv error: {}", name, err);
+                break;
+            }
+        };
+
+        let mut sender = sender.clone();
+
+        // 分发前只读出信封与方法标识；请求格式不对时读不出标识，交给服务回复 InvalidRequest
+        let (id, service, key) = match decode_message::<Message<(MethodKey, IgnoredAny)>>(&frame) {
+            Ok(Message {
+                id,
+                service,
+                payload: (key, _),
+                ..
+            }) => (id, service, Some(key)),
+            Err(_) => match decode_message::<Message<IgnoredAny>>(&frame) {
+                Ok(Message { id, service, .. }) => (id, service, None),
+                Err(err) => {
+                    tracing::error!("{}::serve decode error: {}", name, err);
+                    continue;
                 }
+            },
+        };
+
+        let (route, limit, policy) = match routes.get(service as usize) {
+            Some(Some(route)) => route,
+            _ => {
+                let err = Error::new(ErrorKind::UnknownService, format!("{}::serve unknown service #{}", name, service));
+                send_error(name, &mut sender, id, err).await;
+                continue;
+            }
+        };
+
+        let method = match key.map(|key| (route.resolve_method(&key), key)) {
+            Some((Some(method), _)) => method,
+            Some((None, key)) => {
+                let err = Error::new(ErrorKind::UnknownMethod, format!("{}::{} unknown method", route.name(), key));
+                send_error(name, &mut sender, id, err).await;
+                continue;
+            }
+            None => String::new(),
+        };
+
+        if let Some(policy) = policy {
+            if !policy.is_allowed(ctx.peer_identity(), route.name(), &method) {
+                let caller = match ctx.peer_identity() {
+                    Some(identity) => format!("{} ({})", identity.subject, identity.fingerprint),
+                    None => "anonymous".to_string(),
+                };
+                tracing::warn!(
+                    "{}::serve permission denied: {} calling {}/{} from {:?}",
+                    name,
+                    caller,
+                    route.name(),
+                    method,
+                    ctx.peer_addr()
+                );
+                let err = Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("{}::serve permission denied: {}/{}", name, route.name(), method),
+                );
+                send_error(name, &mut sender, id, err).await;
+                continue;
             }
+        }
 
-            anyhow::Result::<()>::Ok(())
+        let permit = match limit {
+            Some(limit) => limit.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let response = route.call(ctx, id, &frame);
+        let name = route.name();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Some(frame) = response.await {
+                if let Err(err) = sender.send(frame).await {
+                    tracing::error!("{}::serve send error: {}", name, err);
+                }
+            }
         });
+    }
+}
 
+async fn send_error(name: &str, sender: &mut futures::channel::mpsc::Sender<Frame>, id: u64, err: Error) {
+    match encode_message(&Message::new(id, Result::<()>::Err(err))) {
+        Ok(frame) => {
+            if let Err(err) = sender.send(frame).await {
+                tracing::error!("{}::serve send error: {}", name, err);
+            }
+        }
+        Err(err) => tracing::error!("{}::serve encode error: {}", name, err),
+    }
+}
+
+// --- 客户端 ---
+
+type LocalRouteFn<Req, Resp> = dyn Fn(Req) -> BoxFuture<'static, Resp> + Send + Sync;
+
+/// 客户端发送请求的方式
+pub enum ClientTransport<Req, Resp> {
+    /// 编码后通过会话发送
+    Session(Session),
+    /// 在进程内直接处理，由 `*Client::local` 构造
+    Local(LocalTransport<Req, Resp>),
+}
+
+impl<Req, Resp> Clone for ClientTransport<Req, Resp> {
+    fn clone(&self) -> Self {
+        match self {
+            ClientTransport::Session(session) => ClientTransport::Session(session.clone()),
+            ClientTransport::Local(local) => ClientTransport::Local(local.clone()),
+        }
+    }
+}
+
+/// 在进程内把请求直接交给服务的 `route`，默认不经过编解码
+///
+/// 与远程调用一样，每个请求在独立的任务中处理：调用方超时或取消不会中断正在处理的请求。
+pub struct LocalTransport<Req, Resp> {
+    route: Arc<LocalRouteFn<Req, Resp>>,
+    serialize: bool,
+}
+
+impl<Req, Resp> LocalTransport<Req, Resp> {
+    /// 与 [`Service::new`] 相同，`route` 返回的 future 每个请求被 box 一次
+    pub fn new<F, Fut>(route: F) -> Self
+    where
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Resp> + Send + 'static,
+    {
+        Self {
+            route: Arc::new(move |req| route(req).boxed()),
+            serialize: false,
+        }
+    }
+
+    /// 请求与响应仍编解码一次，用于调试时发现无法序列化的类型
+    pub fn with_serialization(mut self, serialize: bool) -> Self {
+        self.serialize = serialize;
         self
     }
+}
+
+impl<Req, Resp> Clone for LocalTransport<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self {
+            route: self.route.clone(),
+            serialize: self.serialize,
+        }
+    }
+}
+
+/// 客户端的调用选项，通过 `*Client::with_timeout` 与 `*Client::with_metadata` 设置
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    /// 等待响应的时间，默认为 5 秒；服务端支持时一并发送，作为请求的截止时间
+    pub timeout: std::time::Duration,
+    /// 随每个请求发送的元数据，服务端通过 [`Context::metadata`] 读取
+    pub metadata: Metadata,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: std::time::Duration::from_secs(5),
+            metadata: Metadata::new(),
+        }
+    }
+}
+
+/// `#[rpc_service(typed_name)]` 的服务在每个实例化的请求枚举上实现，给出该实例化的服务名
+///
+/// 服务名需要在所有实例化之间唯一，例如：
+///
+/// ```ignore
+/// impl nitrogen::TypeName for KvStoreRequest<String, u64, u32> {
+///     const TYPE_NAME: &'static str = "KvStore<String, u64, u32>";
+/// }
+/// ```
+pub trait TypeName {
+    const TYPE_NAME: &'static str;
+}
+
+// RpcServiceClient 通过 rpc_service 自动实现
+
+pub trait RpcServiceClient<Req, Resp>: Sync
+where
+    Req: serde::Serialize + serde::de::DeserializeOwned + RpcRequest<Resp> + Send + 'static,
+    Resp: serde::Serialize + RpcResponse + Send + 'static,
+{
+    const NAME: &'static str;
+
+    /// 握手与分发实际使用的服务名，`#[rpc_service(typed_name)]` 时带有类型实例化
+    fn name() -> &'static str {
+        Self::NAME
+    }
+
+    fn transport(&self) -> &ClientTransport<Req, Resp>;
+
+    fn options(&self) -> &CallOptions;
 
     #[doc(hidden)]
-    async fn request(&self, req: Req) -> Result<Resp> {
-        let (tx, rx) = oneshot::channel::<Resp>();
-        self.tx()
-            .send((req, tx))
-            .await
-            .map_err(|err| Error(format!("{}Client::request send error: {}", Self::NAME, err)))?;
-        match tokio::time::timeout(std::time::Duration::from_secs(5), rx).await {
-            Ok(Ok(res)) => Ok(res),
-            Ok(Err(err)) => Err(Error(format!("{}Client::request recv error: {}", Self::NAME, err))),
-            Err(err) => Err(Error(format!("{}Client::request timeout error: {}", Self::NAME, err))),
+    fn request(&self, req: Req) -> impl Future<Output = Result<Resp>> + Send {
+        async move {
+            let options = self.options();
+            let response = match self.transport() {
+                ClientTransport::Session(session) => Either::Left(session_request(Self::name(), session, options, req)),
+                ClientTransport::Local(local) => Either::Right(local_request(Self::name(), local, options, req)),
+            };
+
+            match tokio::time::timeout(options.timeout, response).await {
+                Ok(result) => result,
+                Err(err) => Err(Error::new(
+                    ErrorKind::Timeout,
+                    format!("{}Client::request timeout error: {}", Self::name(), err),
+                )),
+            }
         }
     }
 }
+
+async fn session_request<Req, Resp>(name: &'static str, session: &Session, options: &CallOptions, req: Req) -> Result<Resp>
+where
+    Req: serde::Serialize + RpcRequest<Resp>,
+{
+    let service = session.service(name).ok_or_else(|| {
+        Error::new(
+            ErrorKind::UnknownService,
+            format!("{}Client::request error: service is not requested by the session", name),
+        )
+    })?;
+
+    let id = session.next_id();
+    let decoder = req.response_decoder();
+    let mut message = Message::new(id, req).with_service(service);
+    if session.has_feature(CONTEXT_FEATURE).await {
+        message.timeout = Some(options.timeout.as_millis() as u64);
+        message.metadata = options.metadata.clone();
+    }
+    let frame = encode_message(&message).map_err(|err| Error::new(ErrorKind::InvalidRequest, format!("{}Client::request encode error: {}", name, err)))?;
+
+    let frame = session.call(service, id, frame).await?;
+
+    decoder(&frame)
+        .map(|Message { payload, .. }| payload)
+        .map_err(|err| Error::new(ErrorKind::InvalidResponse, format!("{}Client::request decode error: {}", name, err)))
+}
+
+async fn local_request<Req, Resp>(name: &'static str, local: &LocalTransport<Req, Resp>, options: &CallOptions, req: Req) -> Result<Resp>
+where
+    Req: serde::Serialize + serde::de::DeserializeOwned + RpcRequest<Resp>,
+    Resp: serde::Serialize + Send + 'static,
+{
+    let decoder = req.response_decoder();
+    let req = if local.serialize {
+        encode_message(&Message::new(0, req))
+            .and_then(|frame| decode_message::<Message<Req>>(&frame))
+            .map(|Message { payload, .. }| payload)
+            .map_err(|err| Error::new(ErrorKind::InvalidRequest, format!("{}Client::local request error: {}", name, err)))?
+    } else {
+        req
+    };
+
+    let ctx = Context::detached().for_request(0, options.metadata.clone(), Some(options.timeout));
+    let resp = tokio::spawn(ctx.run((local.route)(req)))
+        .await
+        .map_err(|err| Error::new(ErrorKind::Transport, format!("{}Client::local route error: {}", name, err)))?;
+
+    if local.serialize {
+        encode_message(&Message::new(0, resp))
+            .and_then(|frame| decoder(&frame))
+            .map(|Message { payload, .. }| payload)
+            .map_err(|err| Error::new(ErrorKind::InvalidResponse, format!("{}Client::local response error: {}", name, err)))
+    } else {
+        Ok(resp)
+    }
+}
@@ -0,0 +1,455 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+// 描述由 rpc_service 在编译期生成，以 `MyServiceRequest::SCHEMA` 访问。
+// 导出为 JSON 后可以提交到仓库，在代码评审中对比线上接口的变化。
+
+/// 服务的描述
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceSchema {
+    pub name: Cow<'static, str>,
+    /// trait 上的文档注释
+    pub docs: Cow<'static, str>,
+    pub methods: Cow<'static, [MethodSchema]>,
+}
+
+/// 方法的描述
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodSchema {
+    /// 线上名称，即 `#[rpc(name = "...")]` 或方法名
+    pub name: Cow<'static, str>,
+    /// Rust 中的方法名
+    pub rust_name: Cow<'static, str>,
+    /// `#[rpc(id = N)]` 指定的数字 id
+    pub id: Option<u32>,
+    pub docs: Cow<'static, str>,
+    pub args: Cow<'static, [ArgSchema]>,
+    /// 返回值类型，按源码中的写法
+    pub returns: Cow<'static, str>,
+}
+
+/// 参数的描述
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgSchema {
+    pub name: Cow<'static, str>,
+    /// 参数类型，按源码中的写法
+    pub ty: Cow<'static, str>,
+    /// `#[rpc(default)]` 的默认值表达式，未标注时为空
+    pub default: Option<Cow<'static, str>>,
+}
+
+impl ServiceSchema {
+    /// 按线上名称查找方法
+    pub fn method(&self, name: &str) -> Option<&MethodSchema> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    /// 格式化的 JSON，字段顺序固定，便于提交到仓库后对比
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("ServiceSchema is always serializable")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+// --- 兼容性检查 ---
+
+/// 变更对已部署对端的影响
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Compatibility {
+    Compatible,
+    Breaking,
+    /// 无法从描述判断，例如自定义类型内部字段或变体的顺序
+    Unverified,
+}
+
+/// 新旧描述之间的一处变更
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaChange {
+    pub compatibility: Compatibility,
+    pub service: String,
+    /// 变更所在的方法（旧描述中的线上名称），服务级的变更为空
+    pub method: Option<String>,
+    pub message: String,
+}
+
+impl SchemaChange {
+    pub fn is_breaking(&self) -> bool {
+        self.compatibility == Compatibility::Breaking
+    }
+
+    pub fn is_unverified(&self) -> bool {
+        self.compatibility == Compatibility::Unverified
+    }
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let compatibility = match self.compatibility {
+            Compatibility::Compatible => "compatible",
+            Compatibility::Breaking => "breaking",
+            Compatibility::Unverified => "unverified",
+        };
+        match &self.method {
+            Some(method) => write!(f, "{}: {}::{}: {}", compatibility, self.service, method, self.message),
+            None => write!(f, "{}: {}: {}", compatibility, self.service, self.message),
+        }
+    }
+}
+
+/// 检查以 `new` 替换 `old` 后，按 `old` 构建的客户端能否继续调用
+///
+/// 请求按方法标识与参数位置编码，因此方法的顺序不影响兼容性；
+/// 方法按旧客户端发送的标识匹配：指定了 id 的方法按 id，否则按线上名称。
+/// 类型按源码中的写法比较，`std::string::String` 与 `String` 视为相同；
+/// 描述中没有自定义类型的字段与变体，新旧两版都用到的自定义类型各报告一次 [`Compatibility::Unverified`]。
+pub fn check_compatibility(old: &ServiceSchema, new: &ServiceSchema) -> Vec<SchemaChange> {
+    let mut changes = vec![];
+    let mut change = |compatibility, method: Option<&str>, message: String| {
+        changes.push(SchemaChange {
+            compatibility,
+            service: old.name.to_string(),
+            method: method.map(str::to_string),
+            message,
+        })
+    };
+
+    if old.name != new.name {
+        change(Compatibility::Breaking, None, format!("service renamed to `{}`", new.name));
+    }
+
+    let mut custom_types = vec![];
+
+    for old_method in old.methods.iter() {
+        let method = Some(old_method.name.as_ref());
+        let new_method = new.methods.iter().find(|new_method| match old_method.id {
+            Some(id) => new_method.id == Some(id),
+            None => new_method.name == old_method.name,
+        });
+
+        let Some(new_method) = new_method else {
+            let message = match old_method.id {
+                Some(id) => format!("method with id {} removed", id),
+                None => "method removed".to_string(),
+            };
+            change(Compatibility::Breaking, method, message);
+            continue;
+        };
+
+        if new_method.name != old_method.name {
+            change(Compatibility::Compatible, method, format!("wire name changed to `{}`", new_method.name));
+        }
+        if old_method.id.is_none() && new_method.id.is_some() {
+            change(Compatibility::Compatible, method, format!("id {} assigned", new_method.id.unwrap_or_default()));
+        }
+
+        for index in 0..old_method.args.len().max(new_method.args.len()) {
+            match (old_method.args.get(index), new_method.args.get(index)) {
+                (Some(old_arg), Some(new_arg)) => {
+                    custom_types.extend(type_paths(&new_arg.ty).into_iter().filter(|path| !is_builtin_type(path)));
+                    if normalize_type(&old_arg.ty) != normalize_type(&new_arg.ty) {
+                        let message = format!("parameter #{} `{}` type changed from `{}` to `{}`", index, old_arg.name, old_arg.ty, new_arg.ty);
+                        change(Compatibility::Breaking, method, message);
+                    }
+                    if old_arg.default.is_some() && new_arg.default.is_none() {
+                        let message = format!("parameter #{} `{}` no longer has a default", index, new_arg.name);
+                        change(Compatibility::Breaking, method, message);
+                    }
+                    if old_arg.name != new_arg.name {
+                        let message = format!("parameter #{} renamed from `{}` to `{}`", index, old_arg.name, new_arg.name);
+                        change(Compatibility::Compatible, method, message);
+                    }
+                }
+                (None, Some(new_arg)) if new_arg.default.is_some() => {
+                    let message = format!("parameter #{} `{}` added with a default", index, new_arg.name);
+                    change(Compatibility::Compatible, method, message);
+                }
+                (None, Some(new_arg)) => {
+                    let message = format!("required parameter #{} `{}` added without a default", index, new_arg.name);
+                    change(Compatibility::Breaking, method, message);
+                }
+                (Some(old_arg), None) => {
+                    let message = format!("trailing parameter #{} `{}` removed, old clients' value is ignored", index, old_arg.name);
+                    change(Compatibility::Compatible, method, message);
+                }
+                (None, None) => {}
+            }
+        }
+
+        custom_types.extend(type_paths(&new_method.returns).into_iter().filter(|path| !is_builtin_type(path)));
+        if normalize_type(&old_method.returns) != normalize_type(&new_method.returns) {
+            let message = format!("return type changed from `{}` to `{}`", old_method.returns, new_method.returns);
+            change(Compatibility::Breaking, method, message);
+        }
+    }
+
+    for new_method in new.methods.iter() {
+        let existed = old.methods.iter().any(|old_method| match old_method.id {
+            Some(id) => new_method.id == Some(id),
+            None => new_method.name == old_method.name,
+        });
+        if !existed {
+            change(Compatibility::Compatible, Some(new_method.name.as_ref()), "method added".to_string());
+        }
+    }
+
+    custom_types.sort();
+    custom_types.dedup();
+    for ty in custom_types {
+        change(
+            Compatibility::Unverified,
+            None,
+            format!("type `{}` is compared by name only, check that its fields and variants keep their order", ty),
+        );
+    }
+
+    changes
+}
+
+/// 路径只保留最后一段的标准库前缀
+const STD_ROOTS: &[&str] = &["std", "core", "alloc"];
+
+/// 编码由 serde 固定的标准库与 nitrogen 类型
+const BUILTIN_TYPES: &[&str] = &[
+    "bool", "char", "str", "String", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64", "Vec", "VecDeque",
+    "Option", "Result", "HashMap", "BTreeMap", "HashSet", "BTreeSet", "Box", "Arc", "Rc", "Cow", "Duration", "Blob", "Value", "Error",
+];
+
+/// 类型中的一个词法单元：路径、生命周期或标点
+fn type_tokens(ty: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = ty.chars().peekable();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = c.to_string();
+        if is_word(c) || c == '\'' || (c == ':' && chars.peek() == Some(&':')) {
+            // 路径的各段连同 `::` 合并为一个单元，`::std::vec::Vec` 也是一个
+            loop {
+                match chars.peek() {
+                    Some(&next) if is_word(next) => token.push(next),
+                    Some(&':') if token.ends_with(':') || c != '\'' => token.push(':'),
+                    Some(&next) if next.is_whitespace() => {
+                        let rest = chars.clone().skip_while(|c| c.is_whitespace()).collect::<String>();
+                        if !(rest.starts_with("::") || token.ends_with("::")) {
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
+                chars.next();
+            }
+        }
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// 去掉标准库路径前缀与多余的空白：`std::vec::Vec<u8>` 写作 `Vec<u8>`
+fn normalize_type(ty: &str) -> String {
+    let mut output = String::new();
+    for token in type_tokens(ty) {
+        let token = short_path(&token);
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '\'');
+        if is_word(output.chars().last()) && is_word(token.chars().next()) {
+            output.push(' ');
+        }
+        output.push_str(token);
+        if token == "," {
+            output.push(' ');
+        }
+    }
+    output
+}
+
+fn short_path(path: &str) -> &str {
+    let segments = path.trim_start_matches("::").split("::").collect::<Vec<_>>();
+    match segments.as_slice() {
+        [root, .., last] if STD_ROOTS.contains(root) => last,
+        _ => path,
+    }
+}
+
+/// 类型中出现的路径，不含生命周期与 `dyn`、`mut` 等关键字
+fn type_paths(ty: &str) -> Vec<String> {
+    type_tokens(ty)
+        .into_iter()
+        .filter(|token| token.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':'))
+        .filter(|token| !matches!(token.as_str(), "dyn" | "mut" | "const" | "impl"))
+        .map(|token| short_path(&token).to_string())
+        .collect()
+}
+
+fn is_builtin_type(path: &str) -> bool {
+    let path = path.trim_start_matches("::");
+    match path.split_once("::") {
+        Some(("nitrogen", name)) => ["Result", "Error", "Blob", "Value"].contains(&name),
+        Some(_) => false,
+        None => BUILTIN_TYPES.contains(&path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arg(name: &'static str, ty: &'static str, default: Option<&'static str>) -> ArgSchema {
+        ArgSchema {
+            name: name.into(),
+            ty: ty.into(),
+            default: default.map(Cow::Borrowed),
+        }
+    }
+
+    fn method(name: &'static str, id: Option<u32>, args: Vec<ArgSchema>, returns: &'static str) -> MethodSchema {
+        MethodSchema {
+            name: name.into(),
+            rust_name: name.into(),
+            id,
+            docs: "".into(),
+            args: args.into(),
+            returns: returns.into(),
+        }
+    }
+
+    fn schema(methods: Vec<MethodSchema>) -> ServiceSchema {
+        ServiceSchema {
+            name: "Storage".into(),
+            docs: "".into(),
+            methods: methods.into(),
+        }
+    }
+
+    fn get(id: Option<u32>, args: Vec<ArgSchema>, returns: &'static str) -> MethodSchema {
+        method("get", id, args, returns)
+    }
+
+    /// 名称、旧描述、新描述与预期的变更
+    type Case<'a> = (&'static str, &'a ServiceSchema, ServiceSchema, Vec<(Compatibility, &'static str)>);
+
+    #[test]
+    fn classifies_changes() {
+        use Compatibility::*;
+
+        let key = || arg("key", "String", None);
+        let old = schema(vec![get(None, vec![key()], "Option<Vec<u8>>")]);
+        let old_with_id = schema(vec![get(Some(1), vec![key()], "Option<Vec<u8>>")]);
+        let old_custom = schema(vec![get(None, vec![key()], "(Vec<Item>, Page)")]);
+
+        let cases: Vec<Case> = vec![
+            ("unchanged", &old, old.clone(), vec![]),
+            ("removed method", &old, schema(vec![]), vec![(Breaking, "method removed")]),
+            (
+                "removed method with id",
+                &old_with_id,
+                schema(vec![get(None, vec![key()], "Option<Vec<u8>>")]),
+                vec![(Breaking, "method with id 1 removed"), (Compatible, "method added")],
+            ),
+            (
+                "changed param type",
+                &old,
+                schema(vec![get(None, vec![arg("key", "u64", None)], "Option<Vec<u8>>")]),
+                vec![(Breaking, "parameter #0 `key` type changed from `String` to `u64`")],
+            ),
+            (
+                "new param with default",
+                &old,
+                schema(vec![get(
+                    None,
+                    vec![key(), arg("ttl", "Option<u64>", Some("Default::default()"))],
+                    "Option<Vec<u8>>",
+                )]),
+                vec![(Compatible, "parameter #1 `ttl` added with a default")],
+            ),
+            (
+                "new param without default",
+                &old,
+                schema(vec![get(None, vec![key(), arg("ttl", "u64", None)], "Option<Vec<u8>>")]),
+                vec![(Breaking, "required parameter #1 `ttl` added without a default")],
+            ),
+            ("id assigned", &old, old_with_id.clone(), vec![(Compatible, "id 1 assigned")]),
+            (
+                "rename under an id",
+                &old_with_id,
+                schema(vec![method("fetch", Some(1), vec![key()], "Option<Vec<u8>>")]),
+                vec![(Compatible, "wire name changed to `fetch`")],
+            ),
+            (
+                "rename without an id",
+                &old,
+                schema(vec![method("fetch", None, vec![key()], "Option<Vec<u8>>")]),
+                vec![(Breaking, "method removed"), (Compatible, "method added")],
+            ),
+            (
+                "changed return type",
+                &old,
+                schema(vec![get(None, vec![key()], "Vec<u8>")]),
+                vec![(Breaking, "return type changed from `Option<Vec<u8>>` to `Vec<u8>`")],
+            ),
+            (
+                "std path spelled out",
+                &old,
+                schema(vec![get(None, vec![arg("key", "std::string::String", None)], "Option<::std::vec::Vec<u8>>")]),
+                vec![],
+            ),
+            (
+                "custom types",
+                &old_custom,
+                schema(vec![get(None, vec![key(), arg("page", "Option<Page>", Some("None"))], "(Vec<Item>, Page)")]),
+                vec![
+                    (Compatible, "parameter #1 `page` added with a default"),
+                    (
+                        Unverified,
+                        "type `Item` is compared by name only, check that its fields and variants keep their order",
+                    ),
+                    (
+                        Unverified,
+                        "type `Page` is compared by name only, check that its fields and variants keep their order",
+                    ),
+                ],
+            ),
+        ];
+
+        for (name, old, new, expected) in cases {
+            let changes = check_compatibility(old, &new);
+            let changes = changes.iter().map(|change| (change.compatibility, change.message.as_str())).collect::<Vec<_>>();
+            assert_eq!(changes, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn reports_method_of_old_schema() {
+        let old = schema(vec![get(Some(1), vec![], "()")]);
+        let new = schema(vec![method("fetch", Some(1), vec![], "u32")]);
+
+        let changes = check_compatibility(&old, &new);
+        assert!(changes
+            .iter()
+            .all(|change| change.service == "Storage" && change.method.as_deref() == Some("get")));
+        assert_eq!(changes[1].to_string(), "breaking: Storage::get: return type changed from `()` to `u32`");
+    }
+
+    #[test]
+    fn normalizes_types() {
+        let cases = [
+            ("std::collections::HashMap<String, Vec<u8>>", "HashMap<String, Vec<u8>>"),
+            ("::core::option::Option< u32 >", "Option<u32>"),
+            ("&'static  str", "&'static str"),
+            ("Box<dyn std::error::Error>", "Box<dyn Error>"),
+            ("app::model::Item", "app::model::Item"),
+        ];
+        for (ty, expected) in cases {
+            assert_eq!(normalize_type(ty), expected, "{}", ty);
+        }
+
+        assert_eq!(type_paths("Result<app::Item, nitrogen::Error>"), ["Result", "app::Item", "nitrogen::Error"]);
+        assert!(!type_paths("&'a std::vec::Vec<u8>").iter().any(|path| !is_builtin_type(path)));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::{
+    channel::{mpsc, oneshot},
+    SinkExt, StreamExt,
+};
+use nitrogen_utils::{channel_sender_with_sink, decode_message, framed_tokio_io, CodecConfig, Frame, FramedTokioIO};
+use serde::de::IgnoredAny;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{watch, OnceCell},
+};
+
+use crate::{client_handshake, Accepted, Context, Error, ErrorKind, Message, Result, Service, FEATURES};
+
+type Call = (u32, u64, Frame, oneshot::Sender<Result<Frame>>);
+
+/// 清理已放弃等待（如超时）的调用的间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 一条流上的客户端会话
+///
+/// 握手时请求多个服务，这些服务的客户端通过 `*Client::with_session` 共享同一条流和同一个后台任务：
+///
+/// ```ignore
+/// let session = nitrogen::Session::new(stream, &[MyServiceClient::NAME, OtherServiceClient::NAME], CodecConfig::default());
+/// let my_client = MyServiceClient::with_session(&session);
+/// let other_client = OtherServiceClient::with_session(&session);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Session {
+    services: Arc<[String]>,
+    /// 握手协商出的可选特性，握手完成前为 `None`
+    features: watch::Receiver<Option<Arc<[String]>>>,
+    cursor: Arc<AtomicU64>,
+    tx: mpsc::Sender<Call>,
+}
+
+impl Session {
+    /// 在后台任务中完成握手；握手失败时，之后的每个请求都以该错误返回
+    pub fn new<S>(mut stream: S, services: &[&str], config: CodecConfig) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel::<Call>(128);
+        let (features_tx, features) = watch::channel(None);
+        let session = Self::from_parts(services, features, tx);
+        let services = session.services.clone();
+
+        tokio::spawn(async move {
+            let names = services.iter().map(String::as_str).collect::<Vec<_>>();
+            match client_handshake(&mut stream, &names, &config).await {
+                Ok((codec, accepted)) => {
+                    let _ = features_tx.send(Some(accepted.features.clone().into()));
+                    run(&services, &accepted, framed_tokio_io(stream, codec), rx).await
+                }
+                Err(err) => {
+                    tracing::error!("Session::handshake error: {}", err);
+                    drop(features_tx);
+                    while let Some((_, _, _, notify)) = rx.next().await {
+                        let _ = notify.send(Err(err.clone()));
+                    }
+                }
+            }
+        });
+
+        session
+    }
+
+    /// 完成握手后返回，握手失败时直接返回错误
+    pub async fn connect<S>(mut stream: S, services: &[&str], config: CodecConfig) -> Result<Self>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (codec, accepted) = client_handshake(&mut stream, services, &config).await?;
+
+        let (tx, rx) = mpsc::channel::<Call>(128);
+        let (_, features) = watch::channel(Some(accepted.features.clone().into()));
+        let session = Self::from_parts(services, features, tx);
+        let services = session.services.clone();
+
+        tokio::spawn(async move { run(&services, &accepted, framed_tokio_io(stream, codec), rx).await });
+
+        Ok(session)
+    }
+
+    /// 不经过流，由 `handler` 在进程内处理已编码的请求
+    ///
+    /// `handler` 的参数为服务序号、请求 id 与请求帧，返回的 `Err` 作为传输层错误交给调用方。
+    /// 视为支持所有可选特性。
+    pub fn with_handler<F, Fut>(services: &[&str], handler: F) -> Self
+    where
+        F: Fn(u32, u64, Frame) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Frame>> + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel::<Call>(128);
+
+        tokio::spawn(async move {
+            while let Some((service, id, frame, notify)) = rx.next().await {
+                let response = handler(service, id, frame);
+                tokio::spawn(async move {
+                    let _ = notify.send(response.await);
+                });
+            }
+        });
+
+        let (_, features) = watch::channel(Some(FEATURES.iter().map(|feature| feature.to_string()).collect()));
+        Self::from_parts(services, features, tx)
+    }
+
+    /// 由进程内的服务处理请求，请求与响应仍经过 MessagePack 编解码，主要用于测试
+    ///
+    /// 工厂服务在第一次请求时创建实例，会话结束时不会调用 `on_disconnect`。
+    pub fn from_services(services: Vec<Service>) -> Self {
+        let names = services.iter().map(|service| service.name()).collect::<Vec<_>>();
+        let services = services.into_iter().map(|service| (service, OnceCell::new())).collect::<Arc<[_]>>();
+        let ctx = Context::detached();
+
+        Self::with_handler(&names, move |service, id, frame| {
+            let (services, ctx) = (services.clone(), ctx.clone());
+            async move {
+                let (route, bound) = services
+                    .get(service as usize)
+                    .ok_or_else(|| Error::new(ErrorKind::UnknownService, format!("Session::call error: unknown service #{}", service)))?;
+                let bound = bound
+                    .get_or_try_init(|| async { route.bind(&ctx).await.map(|(bound, _)| bound) })
+                    .await
+                    .map_err(|err| Error::new(ErrorKind::Handshake, format!("{} on_connect error: {}", route.name(), err)))?;
+
+                bound
+                    .call(&ctx, id, &frame)
+                    .await
+                    .ok_or_else(|| Error::new(ErrorKind::Transport, format!("{}::serve dropped the response", route.name())))
+            }
+        })
+    }
+
+    fn from_parts(services: &[&str], features: watch::Receiver<Option<Arc<[String]>>>, tx: mpsc::Sender<Call>) -> Self {
+        Self {
+            services: services.iter().map(|service| service.to_string()).collect(),
+            features,
+            cursor: Arc::new(AtomicU64::new(0)),
+            tx,
+        }
+    }
+
+    /// 握手时请求的服务
+    pub fn services(&self) -> &[String] {
+        &self.services
+    }
+
+    /// 双方都支持的可选特性；握手尚未完成时等待完成，握手失败时为空
+    pub async fn features(&self) -> Arc<[String]> {
+        let mut features = self.features.clone();
+        let result = match features.wait_for(Option::is_some).await {
+            Ok(features) => features.clone().unwrap_or_default(),
+            Err(_) => Arc::from([]),
+        };
+        result
+    }
+
+    pub async fn has_feature(&self, feature: &str) -> bool {
+        self.features().await.iter().any(|name| name == feature)
+    }
+
+    /// 服务在握手请求中的序号
+    pub fn service(&self, name: &str) -> Option<u32> {
+        self.services.iter().position(|service| service == name).map(|index| index as u32)
+    }
+
+    /// 分配一个在该会话中唯一的请求 id
+    pub fn next_id(&self) -> u64 {
+        self.cursor.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// 发送已编码的请求，并等待 id 相同的响应
+    pub async fn call(&self, service: u32, id: u64, frame: Frame) -> Result<Frame> {
+        let (notify, rx) = oneshot::channel();
+        self.tx
+            .clone()
+            .send((service, id, frame, notify))
+            .await
+            .map_err(|err| Error::new(ErrorKind::Transport, format!("Session::call send error: {}", err)))?;
+        rx.await
+            .map_err(|err| Error::new(ErrorKind::Transport, format!("Session::call recv error: {}", err)))?
+    }
+}
+
+async fn run<S>(services: &[String], accepted: &Accepted, framed_io: FramedTokioIO<S>, mut rx: mpsc::Receiver<Call>)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let accepted = services
+        .iter()
+        .map(|service| accepted.services.iter().any(|name| name == service))
+        .collect::<Vec<_>>();
+
+    let (sender, mut receiver) = framed_io.split();
+    let mut sender = channel_sender_with_sink(sender);
+
+    let mut notifies = HashMap::<u64, oneshot::Sender<Result<Frame>>>::new();
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    sweep.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            call = rx.next() => {
+                let Some((service, id, frame, notify)) = call else {
+                    break;
+                };
+
+                if !accepted.get(service as usize).copied().unwrap_or_default() {
+                    let message = format!("Session::call error: service #{} is not accepted by the server", service);
+                    let _ = notify.send(Err(Error::new(ErrorKind::UnknownService, message)));
+                    continue;
+                }
+
+                match sender.send(frame).await {
+                    Ok(()) => {
+                        notifies.insert(id, notify);
+                    }
+                    Err(err) => {
+                        let _ = notify.send(Err(Error::new(ErrorKind::Transport, format!("Session::call send error: {}", err))));
+                    }
+                }
+            }
+            result = receiver.next() => {
+                let frame = match result {
+                    Some(Ok(frame)) => frame,
+                    None => break,
+                    Some(Err(err)) => {
+                        tracing::error!("Session::call recv error: {}", err);
+                        continue;
+                    }
+                };
+
+                let notify = decode_message::<Message<IgnoredAny>>(&frame).ok().and_then(|Message { id, .. }| notifies.remove(&id));
+                match notify {
+                    Some(notify) => {
+                        let _ = notify.send(Ok(frame));
+                    }
+                    // 调用方已超时放弃，或响应 id 未知
+                    None => tracing::debug!("Session::call recv: response without a waiting caller"),
+                }
+            }
+            _ = sweep.tick() => {
+                notifies.retain(|_, notify| !notify.is_canceled());
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! 经过 Router 的授权检查
+
+use std::sync::Arc;
+
+use nitrogen::{AccessPolicy, CodecConfig, ConnectionInfo, Context, DynamicClient, ErrorKind, PeerIdentity, PeerInfo, Principal, Router, Session};
+
+#[nitrogen::rpc_service]
+pub trait Calc {
+    async fn add(&self, a: i64, b: i64) -> i64;
+    async fn sub(&self, a: i64, b: i64) -> i64;
+}
+
+pub struct CalcImpl;
+
+#[nitrogen::async_trait]
+impl Calc for CalcImpl {
+    async fn add(&self, a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    async fn sub(&self, a: i64, b: i64) -> i64 {
+        a - b
+    }
+}
+
+fn router() -> Arc<Router> {
+    let mut router = Router::new();
+    router.add(CalcImpl.into_service()).set_access_policy(
+        AccessPolicy::new()
+            .allow(Principal::cn("billing"), ["Calc/*"])
+            .allow(Principal::any(), ["Calc/add"]),
+    );
+    Arc::new(router)
+}
+
+fn with_identity(common_name: &str) -> Context {
+    let identity = PeerIdentity {
+        common_name: Some(common_name.into()),
+        ..Default::default()
+    };
+    let peer = PeerInfo {
+        addr: None,
+        identity: Some(identity),
+    };
+    Context::for_stream(Arc::new(ConnectionInfo::new(peer)), 1)
+}
+
+#[tokio::test]
+async fn denied_call_keeps_stream_usable() {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let router = router();
+    tokio::spawn(async move { router.serve_stream(server_io).await });
+
+    let client = CalcClient::new(client_io);
+    assert_eq!(client.add(1, 2).await.unwrap(), 3);
+
+    let err = client.sub(3, 1).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{}", err);
+
+    // 被拒绝的请求不影响同一条流上的其他请求
+    let (a, b, c) = tokio::join!(client.add(2, 2), client.sub(1, 1), client.add(3, 3));
+    assert_eq!(a.unwrap(), 4);
+    assert_eq!(b.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(c.unwrap(), 6);
+}
+
+#[tokio::test]
+async fn allows_by_peer_identity() {
+    for (common_name, allowed) in [("billing", true), ("storage", false)] {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let router = router();
+        let ctx = with_identity(common_name);
+        tokio::spawn(async move { router.serve_stream_with_context(server_io, ctx).await });
+
+        let client = CalcClient::new(client_io);
+        assert_eq!(client.add(1, 2).await.unwrap(), 3);
+        match client.sub(3, 1).await {
+            Ok(value) => assert!(allowed && value == 2),
+            Err(err) => assert!(!allowed && err.kind() == ErrorKind::PermissionDenied, "{}", err),
+        }
+    }
+}
+
+#[tokio::test]
+async fn reports_unknown_methods_before_checking_policy() {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let router = router();
+    tokio::spawn(async move { router.serve_stream(server_io).await });
+
+    let session = Session::new(client_io, &["Calc"], CodecConfig::default());
+    let client = DynamicClient::with_session(&session);
+    // 按名称与按数字 id 请求不存在的方法
+    for method in ["mul", "7"] {
+        let err = client.call("Calc", method, vec![1.into(), 2.into()]).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnknownMethod, "{}: {}", method, err);
+    }
+    assert_eq!(client.call("Calc", "add", vec![1.into(), 2.into()]).await.unwrap(), 3.into());
+}
@@ -0,0 +1,99 @@
+//! 同一服务的新旧定义之间的线上兼容性
+
+use std::sync::{Arc, Mutex};
+
+use nitrogen::ErrorKind;
+
+mod v1 {
+    #[nitrogen::rpc_service(name = "Storage")]
+    pub trait Storage {
+        #[rpc(id = 1)]
+        async fn get(&self, key: String) -> Option<String>;
+    }
+
+    pub struct StorageImpl;
+
+    #[nitrogen::async_trait]
+    impl Storage for StorageImpl {
+        async fn get(&self, key: String) -> Option<String> {
+            Some(format!("v1:{}", key))
+        }
+    }
+}
+
+mod v2 {
+    #[nitrogen::rpc_service(name = "Storage")]
+    pub trait Storage {
+        #[rpc(id = 1)]
+        async fn get(&self, key: String, #[rpc(default = 1)] version: u32) -> Option<String>;
+        #[rpc(id = 2)]
+        async fn delete(&self, key: String) -> bool;
+    }
+
+    pub struct StorageImpl;
+
+    #[nitrogen::async_trait]
+    impl Storage for StorageImpl {
+        async fn get(&self, key: String, version: u32) -> Option<String> {
+            Some(format!("v2:{}@{}", key, version))
+        }
+
+        async fn delete(&self, _key: String) -> bool {
+            true
+        }
+    }
+}
+
+#[tokio::test]
+async fn old_client_gets_default_for_new_trailing_param() {
+    use v2::StorageExt;
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(v2::StorageImpl.serve(server_io));
+
+    let client = v1::StorageClient::new(client_io);
+    assert_eq!(client.get("a".into()).await.unwrap(), Some("v2:a@1".into()));
+}
+
+/// 收集日志输出，测试运行在单线程的运行时上，服务端任务也使用当前线程的日志设置
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Logs {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+#[tokio::test]
+async fn new_client_against_old_server() {
+    use v1::StorageExt;
+
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::fmt().with_writer(move || writer.clone()).with_ansi(false).finish());
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(v1::StorageImpl.serve(server_io));
+
+    let client = v2::StorageClient::new(client_io);
+    // 多传的末尾参数被旧服务端忽略，并记录警告
+    assert_eq!(client.get("a".into(), 7).await.unwrap(), Some("v1:a".into()));
+    assert!(logs.contents().contains("Storage::get ignored 1 trailing arguments"), "{}", logs.contents());
+
+    // 旧服务端不认识 id 2，只回复错误，连接仍可继续使用
+    let err = client.delete("a".into()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnknownMethod, "{}", err);
+    assert_eq!(client.get("b".into(), 1).await.unwrap(), Some("v1:b".into()));
+}
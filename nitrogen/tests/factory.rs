@@ -0,0 +1,174 @@
+//! 工厂服务：按连接或按流创建实例，以及 `on_connect`、`on_disconnect` 的调用时机
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use nitrogen::{
+    async_trait, BiConnnectionAcceptor, BiConnnectionOpener, BiConnnectionSplit, CodecConfig, Context, Router, ServiceFactory, ServiceScope, Session,
+};
+use tokio::{io::DuplexStream, sync::mpsc};
+
+#[nitrogen::rpc_service]
+pub trait Visit {
+    /// 实例的序号与该实例上的调用次数
+    async fn visit(&self) -> (usize, usize);
+}
+
+pub struct VisitImpl {
+    instance: usize,
+    visits: AtomicUsize,
+}
+
+#[nitrogen::async_trait]
+impl Visit for VisitImpl {
+    async fn visit(&self) -> (usize, usize) {
+        (self.instance, self.visits.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    connects: AtomicUsize,
+    disconnects: AtomicUsize,
+}
+
+struct Visits {
+    counts: Arc<Counts>,
+    reject: bool,
+}
+
+impl ServiceFactory for Visits {
+    type Service = VisitImpl;
+
+    async fn on_connect(&self, _ctx: &Context) -> anyhow::Result<VisitImpl> {
+        anyhow::ensure!(!self.reject, "rejected by factory");
+        Ok(VisitImpl {
+            instance: self.counts.connects.fetch_add(1, Ordering::SeqCst),
+            visits: AtomicUsize::new(0),
+        })
+    }
+
+    async fn on_disconnect(&self, _service: &VisitImpl, _ctx: &Context) {
+        self.counts.disconnects.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn router(scope: ServiceScope, reject: bool) -> (Arc<Router>, Arc<Counts>) {
+    let counts = Arc::new(Counts::default());
+    let factory = Visits {
+        counts: counts.clone(),
+        reject,
+    };
+    let mut router = Router::new();
+    router.add(VisitExt::from_factory(factory).with_scope(scope));
+    (Arc::new(router), counts)
+}
+
+// --- 测试用的连接，流由测试依次送入，发送端关闭后连接结束 ---
+
+struct TestConnection(mpsc::UnboundedReceiver<DuplexStream>);
+
+struct NoOpener;
+
+#[async_trait]
+impl BiConnnectionOpener for NoOpener {
+    type Stream = DuplexStream;
+
+    async fn open(&mut self) -> anyhow::Result<Self::Stream> {
+        anyhow::bail!("server side does not open streams")
+    }
+}
+
+struct Streams(mpsc::UnboundedReceiver<DuplexStream>);
+
+#[async_trait]
+impl BiConnnectionAcceptor for Streams {
+    type Stream = DuplexStream;
+
+    async fn accept(&mut self) -> anyhow::Result<Self::Stream> {
+        self.0.recv().await.ok_or_else(|| anyhow::anyhow!("connection closed"))
+    }
+}
+
+impl BiConnnectionSplit for TestConnection {
+    type Opener = NoOpener;
+    type Acceptor = Streams;
+
+    fn split(self) -> (Self::Opener, Self::Acceptor) {
+        (NoOpener, Streams(self.0))
+    }
+}
+
+/// 在同一个连接上打开两条流，各调用一次，返回两次调用的结果
+async fn visit_twice(scope: ServiceScope) -> (Vec<(usize, usize)>, Arc<Counts>) {
+    let (router, counts) = router(scope, false);
+    let (streams, incoming) = mpsc::unbounded_channel();
+    let connection = tokio::spawn(router.serve_connection(TestConnection(incoming)));
+
+    let mut clients = vec![];
+    let mut results = vec![];
+    for _ in 0..2 {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        streams.send(server_io).unwrap();
+        let client = VisitClient::new(client_io);
+        results.push(client.visit().await.unwrap());
+        clients.push(client);
+    }
+    assert_eq!(counts.disconnects.load(Ordering::SeqCst), 0);
+
+    // 关闭连接，等所有流结束
+    drop((clients, streams));
+    tokio::time::timeout(Duration::from_secs(5), connection).await.unwrap().unwrap();
+    (results, counts)
+}
+
+#[tokio::test]
+async fn creates_one_instance_per_connection() {
+    let (results, counts) = visit_twice(ServiceScope::Connection).await;
+    // 两条流共享同一个实例
+    assert_eq!(results, [(0, 1), (0, 2)]);
+    assert_eq!(counts.connects.load(Ordering::SeqCst), 1);
+    assert_eq!(counts.disconnects.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn creates_one_instance_per_stream() {
+    let (results, counts) = visit_twice(ServiceScope::Stream).await;
+    assert_eq!(results, [(0, 1), (1, 1)]);
+    assert_eq!(counts.connects.load(Ordering::SeqCst), 2);
+    assert_eq!(counts.disconnects.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn calls_on_disconnect_when_stream_ends() {
+    let (router, counts) = router(ServiceScope::Stream, false);
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(async move { router.serve_stream(server_io).await });
+
+    let client = VisitClient::new(client_io);
+    assert_eq!(client.visit().await.unwrap(), (0, 1));
+    assert_eq!(counts.disconnects.load(Ordering::SeqCst), 0);
+
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+    assert_eq!(counts.disconnects.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn rejects_handshake_when_on_connect_fails() {
+    for scope in [ServiceScope::Connection, ServiceScope::Stream] {
+        let (router, counts) = router(scope, true);
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move { router.serve_stream(server_io).await });
+
+        let err = Session::connect(client_io, &["Visit"], CodecConfig::default()).await.unwrap_err();
+        assert!(err.to_string().contains("Visit on_connect error: rejected by factory"), "{:?}: {}", scope, err);
+        assert_eq!(counts.connects.load(Ordering::SeqCst), 0);
+        assert_eq!(counts.disconnects.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,65 @@
+//! 进程内的客户端：`local` 与 `local_serialized`
+
+use std::time::Duration;
+
+use nitrogen::ErrorKind;
+use serde::{Deserialize, Serialize, Serializer};
+
+/// 能解码但无法编码的值，只有经过编解码时才会出错
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Opaque(u32);
+
+impl Serialize for Opaque {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("Opaque can not be serialized"))
+    }
+}
+
+#[nitrogen::rpc_service]
+pub trait Calc {
+    async fn add(&self, a: i64, b: i64) -> i64;
+    async fn opaque(&self, value: u32) -> Opaque;
+    async fn sleep(&self, millis: u64);
+}
+
+pub struct CalcImpl;
+
+#[nitrogen::async_trait]
+impl Calc for CalcImpl {
+    async fn add(&self, a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    async fn opaque(&self, value: u32) -> Opaque {
+        Opaque(value)
+    }
+
+    async fn sleep(&self, millis: u64) {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+    }
+}
+
+#[tokio::test]
+async fn calls_implementation_directly() {
+    let client = CalcClient::local(CalcImpl);
+    assert_eq!(client.add(1, 2).await.unwrap(), 3);
+    // 不经过编解码，无法编码的返回值也能传回
+    assert_eq!(client.opaque(7).await.unwrap(), Opaque(7));
+}
+
+#[tokio::test]
+async fn serialized_transport_reports_encode_errors() {
+    let client = CalcClient::local_serialized(CalcImpl);
+    assert_eq!(client.add(1, 2).await.unwrap(), 3);
+
+    let err = client.opaque(7).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidResponse, "{}", err);
+}
+
+#[tokio::test]
+async fn applies_timeout() {
+    let client = CalcClient::local(CalcImpl).with_timeout(Duration::from_millis(20));
+    let err = client.sleep(1000).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Timeout, "{}", err);
+    assert_eq!(client.add(2, 2).await.unwrap(), 4);
+}
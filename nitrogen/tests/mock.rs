@@ -0,0 +1,59 @@
+//! `#[rpc_service(mock)]` 生成的测试替身
+
+use nitrogen::{Error, ErrorKind};
+
+#[nitrogen::rpc_service(mock)]
+pub trait Storage {
+    async fn get(&self, key: &str, range: &[u8]) -> Option<Vec<u8>>;
+    async fn put(&self, key: String, value: Vec<u8>);
+}
+
+#[tokio::test]
+async fn stubs_methods_behind_client() {
+    let mock = MockStorage::new();
+    mock.expect_get().returns(|(key, range)| Ok(Some([key.as_bytes(), &range].concat())));
+    let client = mock.client();
+
+    assert_eq!(client.get("a", &[1, 2]).await.unwrap(), Some(b"a\x01\x02".to_vec()));
+    assert_eq!(client.get("b", &[]).await.unwrap(), Some(b"b".to_vec()));
+
+    // 引用参数记录为拥有所有权的形式
+    assert_eq!(mock.expect_get().calls(), [("a".to_string(), vec![1, 2]), ("b".to_string(), vec![])]);
+    mock.expect_get().assert_called(2);
+    mock.expect_put().assert_called(0);
+}
+
+#[tokio::test]
+async fn returns_errors_to_client() {
+    let mock = MockStorage::new();
+    let client = mock.client();
+
+    let err = client.put("a".into(), vec![]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other, "{}", err);
+    assert!(err.to_string().contains("MockStorage::put is not stubbed"), "{}", err);
+
+    mock.expect_put().returns_error(Error::new(ErrorKind::Timeout, "timeout"));
+    let err = client.put("a".into(), vec![]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Timeout, "{}", err);
+    // 出错的调用同样被记录
+    mock.expect_put().assert_called(2);
+
+    mock.expect_put().reset();
+    mock.expect_put().assert_called(0);
+}
+
+#[tokio::test]
+async fn implements_trait() {
+    let mock = MockStorage::new();
+    mock.expect_get().returns_value(None);
+
+    let storage: &dyn Storage = &mock;
+    assert_eq!(storage.get("k", &[3]).await, None);
+    assert_eq!(mock.expect_get().calls(), [("k".to_string(), vec![3])]);
+}
+
+#[tokio::test]
+#[should_panic(expected = "MockStorage::get was called 0 time(s), expected 1")]
+async fn reports_unexpected_call_count() {
+    MockStorage::new().expect_get().assert_called(1);
+}
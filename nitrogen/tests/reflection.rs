@@ -0,0 +1,44 @@
+//! 内置的反射服务
+
+use std::sync::Arc;
+
+use nitrogen::{ReflectionClient, Router, PROTOCOL_VERSION};
+
+#[nitrogen::rpc_service]
+pub trait Calc {
+    /// 两数之和
+    async fn add(&self, a: i64, b: i64) -> i64;
+}
+
+pub struct CalcImpl;
+
+#[nitrogen::async_trait]
+impl Calc for CalcImpl {
+    async fn add(&self, a: i64, b: i64) -> i64 {
+        a + b
+    }
+}
+
+#[tokio::test]
+async fn lists_registered_schemas() {
+    let mut router = Router::new();
+    // 反射服务之后注册的服务同样列出
+    router.add_reflection().add(CalcImpl.into_service());
+    let router = Arc::new(router);
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move { router.serve_stream(server_io).await });
+
+    let client = ReflectionClient::new(client_io);
+    let services = client.list_services().await.unwrap();
+    let calc = services.iter().find(|schema| schema.name == "Calc").unwrap();
+    assert_eq!(calc, &CalcRequest::SCHEMA);
+    assert_eq!(calc.methods[0].docs, "两数之和");
+
+    assert_eq!(client.describe_service("Calc".into()).await.unwrap().as_ref(), Some(calc));
+    assert_eq!(client.describe_service("Other".into()).await.unwrap(), None);
+
+    let info = client.server_info().await.unwrap();
+    assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+    assert!(info.services.iter().any(|name| name == "Calc"), "{:?}", info.services);
+}
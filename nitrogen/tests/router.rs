@@ -0,0 +1,114 @@
+//! Router：按握手中请求的服务名分发，以及接受连接的循环
+
+use std::{collections::VecDeque, sync::Arc};
+
+use nitrogen::{async_trait, BiConnnectionAcceptor, BiConnnectionOpener, BiConnnectionSplit, BiListener, CodecConfig, Router, Session};
+use tokio::io::DuplexStream;
+
+#[nitrogen::rpc_service]
+pub trait Calc {
+    async fn add(&self, a: i64, b: i64) -> i64;
+}
+
+pub struct CalcImpl;
+
+#[nitrogen::async_trait]
+impl Calc for CalcImpl {
+    async fn add(&self, a: i64, b: i64) -> i64 {
+        a + b
+    }
+}
+
+#[nitrogen::rpc_service]
+pub trait Echo {
+    async fn echo(&self, value: String) -> String;
+}
+
+pub struct EchoImpl;
+
+#[nitrogen::async_trait]
+impl Echo for EchoImpl {
+    async fn echo(&self, value: String) -> String {
+        value
+    }
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.add(CalcImpl.into_service()).add(EchoImpl.into_service());
+    router
+}
+
+#[tokio::test]
+async fn dispatches_services_on_one_stream() {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let router = Arc::new(router());
+    tokio::spawn(async move { router.serve_stream(server_io).await });
+
+    let session = Session::new(client_io, &["Calc", "Echo"], CodecConfig::default());
+    let calc = CalcClient::with_session(&session);
+    let echo = EchoClient::with_session(&session);
+
+    let (sum, value) = tokio::join!(calc.add(1, 2), echo.echo("hi".into()));
+    assert_eq!(sum.unwrap(), 3);
+    assert_eq!(value.unwrap(), "hi");
+}
+
+// --- 只有一条流的连接 ---
+
+struct TestConnection(DuplexStream);
+
+struct NoOpener;
+
+#[async_trait]
+impl BiConnnectionOpener for NoOpener {
+    type Stream = DuplexStream;
+
+    async fn open(&mut self) -> anyhow::Result<Self::Stream> {
+        anyhow::bail!("server side does not open streams")
+    }
+}
+
+struct OneStream(Option<DuplexStream>);
+
+#[async_trait]
+impl BiConnnectionAcceptor for OneStream {
+    type Stream = DuplexStream;
+
+    async fn accept(&mut self) -> anyhow::Result<Self::Stream> {
+        self.0.take().ok_or_else(|| anyhow::anyhow!("connection closed"))
+    }
+}
+
+impl BiConnnectionSplit for TestConnection {
+    type Opener = NoOpener;
+    type Acceptor = OneStream;
+
+    fn split(self) -> (Self::Opener, Self::Acceptor) {
+        (NoOpener, OneStream(Some(self.0)))
+    }
+}
+
+/// 依次返回给定的结果，之后表示监听已关闭
+struct ScriptedListener(VecDeque<anyhow::Result<Option<TestConnection>>>);
+
+#[async_trait]
+impl BiListener for ScriptedListener {
+    type Connection = TestConnection;
+
+    async fn accept(&mut self) -> anyhow::Result<Option<Self::Connection>> {
+        self.0.pop_front().unwrap_or(Ok(None))
+    }
+}
+
+#[tokio::test]
+async fn keeps_accepting_after_connection_error() {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let listener = ScriptedListener(VecDeque::from([Err(anyhow::anyhow!("handshake failed")), Ok(Some(TestConnection(server_io)))]));
+
+    // 第一个连接失败后仍接受第二个连接，监听关闭后返回
+    router().run(listener).await.unwrap();
+
+    let client = CalcClient::new(client_io);
+    assert_eq!(client.add(2, 3).await.unwrap(), 5);
+}
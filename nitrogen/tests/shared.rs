@@ -0,0 +1,74 @@
+//! 通过 `Arc` 共享的实现直接提供服务
+
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+};
+
+use nitrogen::Router;
+
+#[nitrogen::rpc_service]
+pub trait Counter {
+    async fn add(&self, delta: i64) -> i64;
+}
+
+/// 不实现 `Clone`，所有请求共享同一个计数
+#[derive(Default)]
+pub struct CounterImpl(AtomicI64);
+
+#[nitrogen::async_trait]
+impl Counter for CounterImpl {
+    async fn add(&self, delta: i64) -> i64 {
+        self.0.fetch_add(delta, Ordering::SeqCst) + delta
+    }
+}
+
+pub struct NegativeCounter(AtomicI64);
+
+#[nitrogen::async_trait]
+impl Counter for NegativeCounter {
+    async fn add(&self, delta: i64) -> i64 {
+        self.0.fetch_sub(delta, Ordering::SeqCst) - delta
+    }
+}
+
+#[tokio::test]
+async fn serves_trait_object() {
+    for negative in [false, true] {
+        let counter: Arc<dyn Counter> = if negative {
+            Arc::new(NegativeCounter(AtomicI64::new(0)))
+        } else {
+            Arc::new(CounterImpl::default())
+        };
+
+        let mut router = Router::new();
+        router.add(counter.clone().into_service());
+        let router = Arc::new(router);
+
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move { router.serve_stream(server_io).await });
+
+        let client = CounterClient::new(client_io);
+        client.add(2).await.unwrap();
+        let expected = if negative { -5 } else { 5 };
+        assert_eq!(client.add(3).await.unwrap(), expected);
+        // 服务端与调用方持有同一个实例
+        assert_eq!(counter.add(0).await, expected);
+    }
+}
+
+#[tokio::test]
+async fn serves_shared_instance_on_several_streams() {
+    let counter = Arc::new(CounterImpl::default());
+
+    let mut clients = vec![];
+    for _ in 0..2 {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(counter.clone().serve(server_io));
+        clients.push(CounterClient::new(client_io));
+    }
+
+    assert_eq!(clients[0].add(1).await.unwrap(), 1);
+    assert_eq!(clients[1].add(1).await.unwrap(), 2);
+    assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+}
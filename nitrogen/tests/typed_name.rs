@@ -0,0 +1,71 @@
+//! `typed_name`：同一个泛型服务的多种实例化注册到同一个 Router
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use nitrogen::{CodecConfig, Router, Session, TypeName};
+use serde::{de::DeserializeOwned, Serialize};
+
+mod a {
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Key(pub String);
+}
+
+mod b {
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Key(pub u32);
+}
+
+#[nitrogen::rpc_service(typed_name)]
+pub trait Echo<T> {
+    async fn echo(&self, value: T) -> T;
+}
+
+pub struct EchoImpl<T>(PhantomData<T>);
+
+impl<T> Default for EchoImpl<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[nitrogen::async_trait]
+impl<T> Echo<T> for EchoImpl<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    async fn echo(&self, value: T) -> T {
+        value
+    }
+}
+
+// 两个模块中同名的类型各自给出不同的服务名
+impl TypeName for EchoRequest<a::Key> {
+    const TYPE_NAME: &'static str = "Echo<a::Key>";
+}
+
+impl TypeName for EchoRequest<b::Key> {
+    const TYPE_NAME: &'static str = "Echo<b::Key>";
+}
+
+#[tokio::test]
+async fn serves_instantiations_side_by_side() {
+    use nitrogen::RpcServiceClient;
+
+    let mut router = Router::new();
+    router.add(EchoImpl::<a::Key>::default().into_service());
+    router.add(EchoImpl::<b::Key>::default().into_service());
+    let router = Arc::new(router);
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move { router.serve_stream(server_io).await });
+
+    assert_eq!(EchoClient::<a::Key>::name(), "Echo<a::Key>");
+    assert_eq!(EchoClient::<b::Key>::name(), "Echo<b::Key>");
+
+    let session = Session::new(client_io, &["Echo<a::Key>", "Echo<b::Key>"], CodecConfig::default());
+    let client_a = EchoClient::<a::Key>::with_session(&session);
+    let client_b = EchoClient::<b::Key>::with_session(&session);
+    assert_eq!(client_a.echo(a::Key("x".into())).await.unwrap().0, "x");
+    assert_eq!(client_b.echo(b::Key(7)).await.unwrap().0, 7);
+}
@@ -0,0 +1,6 @@
+#[nitrogen::rpc_service]
+pub trait Storage {
+    async fn get<K: serde::Serialize>(&self, key: K) -> Vec<u8>;
+}
+
+fn main() {}
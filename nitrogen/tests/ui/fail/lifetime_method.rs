@@ -0,0 +1,6 @@
+#[nitrogen::rpc_service]
+pub trait Storage {
+    async fn get<'a>(&self, key: &'a str) -> Vec<u8>;
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[nitrogen::rpc_service]
+pub trait Counter {
+    async fn increment(&mut self) -> u64;
+}
+
+fn main() {}
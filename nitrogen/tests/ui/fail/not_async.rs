@@ -0,0 +1,6 @@
+#[nitrogen::rpc_service]
+pub trait Storage {
+    fn get(&self, key: String) -> Vec<u8>;
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+// 所有冲突一次报告
+#[nitrogen::rpc_service]
+pub trait Storage {
+    async fn get_x(&self) -> u32;
+    #[allow(non_snake_case)]
+    async fn getX(&self) -> u32;
+    #[rpc(id = 1)]
+    async fn put(&self, value: u32);
+    #[rpc(id = 1)]
+    async fn delete(&self);
+}
+
+fn main() {}
@@ -0,0 +1,36 @@
+mod app {
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Context {
+        pub user: String,
+    }
+}
+
+mod imported {
+    use super::app::Context;
+
+    // 宏看不到 `use`，`&Context` 按普通参数处理
+    #[nitrogen::rpc_service]
+    pub trait Audit {
+        async fn log(&self, ctx: &Context, line: String);
+    }
+}
+
+#[nitrogen::rpc_service]
+pub trait Storage {
+    async fn get(&self, ctx: &nitrogen::Context, key: String) -> Option<String>;
+    async fn put(&self, ctx: &app::Context, key: String);
+}
+
+fn main() {
+    // `&app::Context` 是普通参数，出现在服务描述与请求中
+    let schema = StorageRequest::SCHEMA;
+    assert_eq!(schema.method("get").unwrap().args.len(), 1);
+    let put = schema.method("put").unwrap();
+    assert_eq!(put.args.len(), 2);
+    assert_eq!(put.args[0].name, "ctx");
+    assert_eq!(imported::AuditRequest::SCHEMA.method("log").unwrap().args.len(), 2);
+
+    let _ = |client: StorageClient| async move {
+        client.put(&app::Context { user: "a".into() }, "key".into()).await
+    };
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+#[nitrogen::rpc_service(native, mock)]
+pub trait Greeter {
+    async fn hello(&self, ctx: &nitrogen::Context, name: &str) -> String;
+    async fn count(&self) -> u32;
+}
+
+struct MyGreeter;
+
+// 原生 async fn，不需要 `#[nitrogen::async_trait]`
+impl Greeter for MyGreeter {
+    async fn hello(&self, _ctx: &nitrogen::Context, name: &str) -> String {
+        format!("hello, {}", name)
+    }
+
+    async fn count(&self) -> u32 {
+        0
+    }
+}
+
+fn main() {
+    let mut router = nitrogen::Router::new();
+    router.add(MyGreeter.into_service()).add(Arc::new(MyGreeter).into_service());
+
+    let _ = |stream: tokio::io::DuplexStream| MyGreeter.serve(stream);
+    let _ = |client: GreeterClient| async move { client.hello("a").await };
+    let _ = GreeterClient::local(MyGreeter);
+    let _ = |mock: MockGreeter| mock.client();
+}
@@ -0,0 +1,29 @@
+#[nitrogen::rpc_service]
+pub trait Geometry {
+    async fn area(&self, (width, height): (u32, u32)) -> u32;
+    async fn origin(&self, Point { x, y }: Point) -> bool;
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Point {
+    x: i32,
+    y: i32,
+}
+
+struct GeometryImpl;
+
+#[nitrogen::async_trait]
+impl Geometry for GeometryImpl {
+    async fn area(&self, (width, height): (u32, u32)) -> u32 {
+        width * height
+    }
+
+    async fn origin(&self, Point { x, y }: Point) -> bool {
+        x == 0 && y == 0
+    }
+}
+
+fn main() {
+    let _ = GeometryExt::into_service(GeometryImpl);
+    let _ = |client: GeometryClient| async move { client.area((2, 3)).await };
+}